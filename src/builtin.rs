@@ -0,0 +1,84 @@
+//! Vanilla data sets compiled into the binary, so the planner works without
+//! running the fetcher against a local game install.
+
+use data_file::{self, DataSet};
+
+/// Game versions with a built-in data set, oldest first.
+pub const GAME_VERSIONS: [(&'static str, &'static str); 2] = [
+    ("1.1", include_str!("../data/vanilla-1.1.data")),
+    ("2.0", include_str!("../data/vanilla-2.0.data"))
+];
+
+/// The latest version, used when no data file is given.
+pub const DEFAULT_VERSION: &'static str = "2.0";
+
+/// Source of the built-in data set for `version`.
+pub fn source(version: &str) -> Option<&'static str> {
+    GAME_VERSIONS.iter().find(|x| x.0 == version).map(|x| x.1)
+}
+
+/// Built-in data set for `version`, `None` for unknown versions.
+pub fn data_set(version: &str) -> Option<DataSet> {
+    source(version).map(|s| match data_file::read_data(s) {
+        Ok(data) => data,
+        Err(why) => panic!("built-in data set {} is broken: {}", version, why)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::collections::HashSet;
+    use preferences::MachinePreferences;
+    use rational::Rational;
+    use research;
+    use solver::{build_dependency_net, make_plan, to_product_recipes, ComponentType};
+    use structs;
+    use super::*;
+
+    #[test]
+    fn every_data_set_plans_smelting_and_fluids() {
+        for &(version, _) in GAME_VERSIONS.iter() {
+            let data = data_set(version).unwrap();
+            let m = to_product_recipes(&data.recipes);
+
+            let plate = &m["iron-plate"];
+            assert_eq!(plate.category, "smelting", "{}", version);
+            assert!(structs::picks_recipe_from_input(&plate.category), "{}", version);
+            let gas = &m["petroleum-gas"];
+            assert_eq!(gas.output_type, ComponentType::Fluid, "{}", version);
+            assert!(gas.compounds.iter().any(|c| c.name == "crude-oil" && c.a_type == ComponentType::Fluid), "{}", version);
+
+            for name in &["iron-plate", "petroleum-gas"] {
+                let mut targets = HashMap::new();
+                targets.insert(name.to_string(), Rational::ONE);
+                let plan = make_plan(&m, &targets, build_dependency_net(&m, vec![name.to_string()]));
+                assert_eq!(plan.compounds[*name].rate, Rational::ONE, "{}", version);
+                assert!(plan.compounds[*name].time.is_some(), "{}", version);
+            }
+        }
+    }
+
+    #[test]
+    fn the_2_0_data_set_has_what_2_0_features_need() {
+        let data = data_set("2.0").unwrap();
+        assert_eq!(structs::rocket_parts_required(data.stamp.game_version.as_deref()), 50);
+        let recipe = |name| data.recipes.iter().find(|x| x.name == name).unwrap();
+        assert_eq!(recipe("space-science-pack").surface_conditions[0].property, "gravity");
+        assert_eq!(recipe("iron-gear-wheel").maximum_productivity, Some(3.0));
+
+        let preferences = MachinePreferences::new().with_machines(&data.machines);
+        assert_eq!(preferences.machine_for(recipe("tungsten-plate")).map(|x| x.name), Ok("foundry"));
+        assert_eq!(preferences.machine_for(recipe("bioflux")).map(|x| x.name), Ok("biochamber"));
+        assert_eq!(preferences.machine_for(recipe("processing-unit")).map(|x| x.name), Ok("assembling-machine-2"));
+
+        let mash = data.items.iter().find(|x| x.name == "yumako-mash").unwrap();
+        assert_eq!((mash.spoil_ticks, mash.spoil_result.as_deref()), (Some(10800.0), Some("spoilage")));
+        assert!(data.items.iter().all(|x| x.weight.is_some()));
+
+        let technologies = research::technologies(&data.technologies);
+        let queue = research::expand_queue(&technologies, &["chemical-science-pack".to_string()], &HashSet::new()).unwrap();
+        assert_eq!(queue.len(), 5);
+        assert_eq!(queue.last().map(|x| &x[..]), Some("chemical-science-pack"));
+    }
+}
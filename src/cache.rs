@@ -0,0 +1,278 @@
+//! Parallel processing of prototype files with an on-disk cache.
+//!
+//! Every file is converted to a piece of text (e.g. its recipes in the data
+//! file format). Results are stored in a cache file keyed on the converter
+//! (e.g. `recipe-Factorio2`, as the recipe text depends on the prototype
+//! format), the file path, size and modification time, so on the next run
+//! only changed files are read and parsed again. Files that are not cached
+//! are processed in parallel.
+//!
+//! Cache file format (lines, tab separated):
+//!
+//! ```text
+//! cache   <version>
+//! file    <converter> <path> <size> <mtime in ns> <number of lines>
+//! <lines of the cached text>
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+/// Bumped when the cached text of a file changes for the same input,
+/// e.g. when the conversion of prototypes changes.
+pub const CACHE_VERSION: u32 = 6;
+
+/// Identity of a file version: size and modification time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileStamp {
+    pub size: u64,
+    pub mtime: u64
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> Result<FileStamp, io::Error> {
+        let metadata = fs::metadata(path)?;
+        let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs() * 1_000_000_000 + x.subsec_nanos() as u64)
+            .unwrap_or(0);
+        Ok(FileStamp { size: metadata.len(), mtime: mtime })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Cache {
+    entries: HashMap<(String, String), (FileStamp, String)>,
+    used: HashSet<(String, String)>,
+    pub hits: usize,
+    pub misses: usize
+}
+
+fn error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn key(converter: &str, path: &Path) -> (String, String) {
+    (converter.to_string(), path.to_string_lossy().into_owned())
+}
+
+impl Cache {
+    pub fn new() -> Cache {
+        Cache::default()
+    }
+
+    /// Reads the cache file. A missing file or a file of another version
+    /// gives an empty cache.
+    pub fn load(path: &Path) -> Result<Cache, io::Error> {
+        let mut s = String::new();
+        match File::open(path) {
+            Ok(mut file) => file.read_to_string(&mut s)?,
+            Err(ref why) if why.kind() == io::ErrorKind::NotFound => return Ok(Cache::new()),
+            Err(why) => return Err(why)
+        };
+        let mut cache = Cache::new();
+        let mut lines = s.lines();
+        if lines.next() != Some(&format!("cache\t{}", CACHE_VERSION)[..]) {
+            return Ok(cache);
+        }
+        while let Some(line) = lines.next() {
+            let parts: Vec<_> = line.split('\t').collect();
+            if parts.len() != 6 || parts[0] != "file" {
+                return Err(error(format!("illegal cache entry: {}", line)));
+            }
+            let number = |s: &str| u64::from_str(s).map_err(|why| error(format!("not a number [{}]: {}", s, why)));
+            let stamp = FileStamp { size: number(parts[3])?, mtime: number(parts[4])? };
+            let mut text = String::new();
+            for _ in 0..number(parts[5])? {
+                match lines.next() {
+                    Some(line) => {
+                        text.push_str(line);
+                        text.push('\n');
+                    },
+                    None => return Err(error(format!("truncated cache entry: {}", parts[2])))
+                }
+            }
+            cache.entries.insert((parts[1].to_string(), parts[2].to_string()), (stamp, text));
+        }
+        Ok(cache)
+    }
+
+    /// Drops entries of files not processed since `load`, e.g. deleted files.
+    pub fn prune(&mut self) {
+        let used = &self.used;
+        self.entries.retain(|name, _| used.contains(name));
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        let mut file = File::create(path)?;
+        writeln!(file, "cache\t{}", CACHE_VERSION)?;
+        for (&(ref converter, ref name), &(stamp, ref text)) in &self.entries {
+            writeln!(file, "file\t{}\t{}\t{}\t{}\t{}", converter, name, stamp.size, stamp.mtime, text.lines().count())?;
+            for line in text.lines() {
+                writeln!(file, "{}", line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Cached text of `path` converted by `converter`, if the file didn't
+    /// change since it was cached.
+    pub fn get(&self, converter: &str, path: &Path, stamp: FileStamp) -> Option<&str> {
+        match self.entries.get(&key(converter, path)) {
+            Some(&(cached, ref text)) if cached == stamp => Some(text),
+            _ => None
+        }
+    }
+
+    pub fn insert(&mut self, converter: &str, path: &Path, stamp: FileStamp, text: String) {
+        self.entries.insert(key(converter, path), (stamp, text));
+    }
+}
+
+/// Applies `f` to every path, using all available cores.
+/// Results are in the order of `paths`.
+pub fn map_parallel<T, F>(paths: &[PathBuf], f: F) -> Vec<T>
+    where T: Send, F: Fn(&Path) -> T + Sync
+{
+    let threads = thread::available_parallelism().map(|x| x.get()).unwrap_or(1).min(paths.len());
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(paths.len()));
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= paths.len() {
+                    break;
+                }
+                let result = f(&paths[i]);
+                results.lock().unwrap().push((i, result));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|x| x.0);
+    results.into_iter().map(|x| x.1).collect()
+}
+
+/// Converts the contents of every file with `convert`, reusing cached results
+/// of unchanged files. `converter` names `convert` and whatever its result
+/// depends on besides the file. Returns the texts in the order of `paths`.
+pub fn process_files<F>(paths: &[PathBuf], cache: &mut Cache, converter: &str, convert: F) -> Result<Vec<String>, io::Error>
+    where F: Fn(&Path, &str) -> String + Sync
+{
+    let mut stamps = Vec::new();
+    for path in paths {
+        stamps.push(FileStamp::of(path)?);
+    }
+    let changed: Vec<_> = (0..paths.len()).filter(|&i| cache.get(converter, &paths[i], stamps[i]).is_none()).collect();
+    let changed_paths: Vec<_> = changed.iter().map(|&i| paths[i].clone()).collect();
+    let converted = map_parallel(&changed_paths, |path| -> Result<String, io::Error> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        Ok(convert(path, &s))
+    });
+    cache.used.extend(paths.iter().map(|x| key(converter, x)));
+    cache.hits += paths.len() - changed.len();
+    cache.misses += changed.len();
+    for (&i, text) in changed.iter().zip(converted) {
+        cache.insert(converter, &paths[i], stamps[i], text?);
+    }
+    Ok(paths.iter().zip(&stamps).map(|(path, &stamp)| cache.get(converter, path, stamp).unwrap().to_string()).collect())
+}
+
+/// Files in `dir` with the extension `extension`, sorted by name.
+pub fn list_files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>, io::Error> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|x| x == extension) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+    use super::*;
+
+    /// A fresh directory for a test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("planner-cache-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn saves_and_loads_entries() {
+        let dir = temp_dir("round-trip");
+        let stamp = FileStamp { size: 12, mtime: 345 };
+        let mut cache = Cache::new();
+        cache.insert("recipe-Legacy", Path::new("a.lua"), stamp, "line 1\nline 2\n".to_string());
+        cache.insert("item", Path::new("a.lua"), stamp, String::new());
+        cache.save(&dir.join("fetch.cache")).unwrap();
+
+        let cache = Cache::load(&dir.join("fetch.cache")).unwrap();
+        assert_eq!(cache.get("recipe-Legacy", Path::new("a.lua"), stamp), Some("line 1\nline 2\n"));
+        assert_eq!(cache.get("item", Path::new("a.lua"), stamp), Some(""));
+        assert_eq!(cache.get("recipe-Factorio2", Path::new("a.lua"), stamp), None);
+        assert_eq!(cache.get("recipe-Legacy", Path::new("a.lua"), FileStamp { size: 12, mtime: 346 }), None);
+        assert!(Cache::load(&dir.join("missing.cache")).unwrap().entries.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_broken_cache_files() {
+        let dir = temp_dir("broken");
+        let path = dir.join("fetch.cache");
+        let header = format!("cache\t{}\n", CACHE_VERSION);
+        let cases = [
+            format!("{}file\titem\ta.lua\t1\t2\t3\nonly one line\n", header),
+            format!("{}file\titem\ta.lua\t1\t2\n", header),
+            format!("{}file\titem\ta.lua\tx\t2\t0\n", header),
+            format!("{}line\n", header)
+        ];
+        for case in cases.iter() {
+            fs::write(&path, case).unwrap();
+            assert!(Cache::load(&path).is_err(), "{}", case);
+        }
+        // a cache of another version is dropped
+        fs::write(&path, "cache\t1\nanything\n").unwrap();
+        assert!(Cache::load(&path).unwrap().entries.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_results_in_order_of_paths() {
+        let paths: Vec<_> = (0..50).map(|i| PathBuf::from(format!("{}", i))).collect();
+        let results = map_parallel(&paths, |path| path.to_string_lossy().into_owned());
+        assert_eq!(results, (0..50).map(|i| i.to_string()).collect::<Vec<_>>());
+
+        let dir = temp_dir("process");
+        let paths: Vec<_> = ["b", "a", "c"].iter().map(|name| {
+            let path = dir.join(name);
+            fs::write(&path, name).unwrap();
+            path
+        }).collect();
+        let mut cache = Cache::new();
+        let upper = |_: &Path, s: &str| s.to_uppercase();
+        assert_eq!(process_files(&paths, &mut cache, "upper", upper).unwrap(), vec!["B", "A", "C"]);
+        assert_eq!((cache.hits, cache.misses), (0, 3));
+        assert_eq!(process_files(&paths, &mut cache, "upper", |_: &Path, _: &str| unreachable!()).unwrap(), vec!["B", "A", "C"]);
+        assert_eq!((cache.hits, cache.misses), (3, 3));
+        assert_eq!(process_files(&paths[..1], &mut cache, "twice", |_: &Path, s: &str| s.repeat(2)).unwrap(), vec!["bb"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
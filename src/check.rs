@@ -0,0 +1,217 @@
+//! Lint checks of a recipe set, run after fetching.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use preferences::MachinePreferences;
+use structs::{self, Recipe};
+use solver::{build_dependency_net, to_product_recipes, Graph};
+
+/// Items that come from mining, pumping or boilers rather than recipes.
+pub const RESOURCES: [&'static str; 10] = [
+    "iron-ore", "copper-ore", "stone", "coal", "uranium-ore", "crude-oil", "water", "steam", "wood", "raw-fish"
+];
+
+#[derive(Debug, PartialEq)]
+pub enum Issue {
+    ConsumedNeverProduced(String),
+    ProducedNeverUsed(String),
+    NoMachine { recipe: String, category: String },
+    TooManyIngredients { recipe: String, ingredients: usize },
+    Cycle(Vec<String>),
+    DuplicateRecipe { name: String, sources: Vec<String> },
+    BadAmount { recipe: String, component: String, amount: f64 }
+}
+
+use self::Issue::*;
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConsumedNeverProduced(ref name) => write!(f, "{} is consumed but never produced", name),
+            ProducedNeverUsed(ref name) => write!(f, "{} is produced but never used", name),
+            NoMachine { ref recipe, ref category } => write!(f, "recipe {}: no machine for category {}", recipe, category),
+            TooManyIngredients { ref recipe, ingredients } => write!(f, "recipe {}: no machine takes {} ingredients", recipe, ingredients),
+            Cycle(ref names) => write!(f, "cycle: {} -> {}", names.join(" -> "), names[0]),
+            DuplicateRecipe { ref name, ref sources } => write!(f, "recipe {} is defined {} times ({})", name, sources.len(), unique(sources).join(", ")),
+            BadAmount { ref recipe, ref component, amount } => write!(f, "recipe {}: {} has amount {}", recipe, component, amount)
+        }
+    }
+}
+
+fn unique(names: &[String]) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for name in names {
+        if !out.contains(name) {
+            out.push(name.clone());
+        }
+    }
+    out
+}
+
+/// Checks recipes grouped by the file they come from (prototype file or data
+/// file); `preferences` has the ingredient limits of the machines.
+pub fn check(files: &[(String, Vec<Recipe>)], preferences: &MachinePreferences) -> Vec<Issue> {
+    let recipes: Vec<&Recipe> = files.iter().flat_map(|x| x.1.iter()).collect();
+    let mut issues = Vec::new();
+
+    let mut sources: HashMap<&str, Vec<String>> = HashMap::new();
+    for &(ref file, ref list) in files {
+        for recipe in list {
+            sources.entry(&recipe.name).or_default().push(file.clone());
+        }
+    }
+    let mut duplicates: Vec<_> = sources.into_iter().filter(|x| x.1.len() > 1).collect();
+    duplicates.sort();
+    for (name, sources) in duplicates {
+        issues.push(DuplicateRecipe { name: name.to_string(), sources: sources });
+    }
+
+    let produced: HashSet<&str> = recipes.iter().flat_map(|r| r.products.iter()).map(|c| &c.name[..]).collect();
+    let consumed: HashSet<&str> = recipes.iter().flat_map(|r| r.ingredients.iter()).map(|c| &c.name[..]).collect();
+    let mut never_produced: Vec<_> = consumed.iter().filter(|x| !produced.contains(*x) && !RESOURCES.contains(*x)).collect();
+    never_produced.sort();
+    issues.extend(never_produced.into_iter().map(|x| ConsumedNeverProduced(x.to_string())));
+    let mut never_used: Vec<_> = produced.iter().filter(|x| !consumed.contains(*x)).collect();
+    never_used.sort();
+    issues.extend(never_used.into_iter().map(|x| ProducedNeverUsed(x.to_string())));
+
+    for recipe in &recipes {
+        if structs::machines_for_category(&recipe.category).is_empty() {
+            issues.push(NoMachine { recipe: recipe.name.clone(), category: recipe.category.clone() });
+        } else if structs::fixed_recipe_machine(&recipe.name).is_none() && preferences.choose_machine(&recipe.category, recipe.ingredients.len()).is_err() {
+            issues.push(TooManyIngredients { recipe: recipe.name.clone(), ingredients: recipe.ingredients.len() });
+        }
+        for comp in recipe.ingredients.iter().chain(recipe.products.iter()) {
+            let amounts = [Some(comp.amount), comp.amount_min, comp.amount_max];
+            if let Some(&Some(amount)) = amounts.iter().find(|x| x.is_some_and(|x| x <= 0.0)) {
+                issues.push(BadAmount { recipe: recipe.name.clone(), component: comp.name.clone(), amount: amount });
+            }
+        }
+    }
+
+    let all: Vec<Recipe> = recipes.into_iter().cloned().collect();
+    let m = to_product_recipes(&all);
+    let graph = build_dependency_net(&m, m.keys().cloned().collect());
+    issues.extend(find_cycles(&graph).into_iter().map(Cycle));
+    issues
+}
+
+/// Strongly connected components of the dependency graph with more than one
+/// vertex (or a self loop), found with Tarjan's algorithm. The depth first
+/// search keeps its own stack, so long chains of modded recipes can't
+/// overflow the thread's stack.
+fn find_cycles(graph: &Graph) -> Vec<Vec<String>> {
+    let mut edges: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in &graph.edges {
+        edges.entry(&edge.from[..]).or_default().push(&edge.to[..]);
+    }
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut low: HashMap<&str, usize> = HashMap::new();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut on_stack: HashSet<&str> = HashSet::new();
+    let mut cycles = Vec::new();
+
+    let mut vertices: Vec<&str> = graph.vertices.iter().map(|x| &x[..]).collect();
+    vertices.sort();
+    for root in vertices {
+        if index.contains_key(root) {
+            continue;
+        }
+        // vertices being visited, with the number of their edges followed
+        let mut path: Vec<(&str, usize)> = vec![(root, 0)];
+        let i = index.len();
+        index.insert(root, i);
+        low.insert(root, i);
+        stack.push(root);
+        on_stack.insert(root);
+        while let Some(&mut (v, ref mut next)) = path.last_mut() {
+            let w = edges.get(v).and_then(|x| x.get(*next)).copied();
+            *next += 1;
+            match w {
+                Some(w) if !index.contains_key(w) => {
+                    let i = index.len();
+                    index.insert(w, i);
+                    low.insert(w, i);
+                    stack.push(w);
+                    on_stack.insert(w);
+                    path.push((w, 0));
+                },
+                Some(w) => {
+                    if on_stack.contains(w) {
+                        let l = low[v].min(index[w]);
+                        low.insert(v, l);
+                    }
+                },
+                None => {
+                    path.pop();
+                    if let Some(&(parent, _)) = path.last() {
+                        let l = low[parent].min(low[v]);
+                        low.insert(parent, l);
+                    }
+                    if low[v] == index[v] {
+                        let mut component = Vec::new();
+                        loop {
+                            let w = stack.pop().unwrap();
+                            on_stack.remove(w);
+                            component.push(w.to_string());
+                            if w == v {
+                                break;
+                            }
+                        }
+                        let self_loop = edges.get(v).is_some_and(|x| x.contains(&v));
+                        if component.len() > 1 || self_loop {
+                            component.reverse();
+                            cycles.push(component);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use data_file;
+    use super::*;
+
+    fn recipe(name: &str, ingredients: &[&str]) -> String {
+        let mut s = format!("recipe\t{}\n", name);
+        for x in ingredients {
+            s.push_str(&format!("ingredient\titem\t{}\t1\n", x));
+        }
+        s + &format!("product\titem\t{}\t1\nend\n", name)
+    }
+
+    fn issues(recipes: &[String]) -> Vec<Issue> {
+        let recipes = data_file::read_recipes(&format!("format\t8\n{}", recipes.concat())).unwrap();
+        check(&[("test.data".to_string(), recipes)], &MachinePreferences::new())
+    }
+
+    #[test]
+    fn finds_cycles_and_self_loops() {
+        let found = issues(&[recipe("a", &["b"]), recipe("b", &["c", "a"]), recipe("c", &["iron-ore"]),
+                             recipe("seed", &["seed"]), recipe("tree", &["seed", "a"])]);
+        let cycles: Vec<_> = found.iter().filter_map(|x| match *x { Cycle(ref names) => Some(names.clone()), _ => None }).collect();
+        assert_eq!(cycles.len(), 2);
+        assert!(cycles.contains(&vec!["seed".to_string()]));
+        let mut ab = cycles.into_iter().find(|x| x.len() == 2).unwrap();
+        ab.sort();
+        assert_eq!(ab, vec!["a", "b"]);
+        assert!(found.contains(&ProducedNeverUsed("tree".to_string())));
+    }
+
+    #[test]
+    fn finds_items_nothing_produces() {
+        let found = issues(&[recipe("gear", &["iron-plate"]), recipe("iron-plate", &["iron-ore"]), recipe("engine", &["gear", "pipe"])]);
+        assert_eq!(found, vec![ConsumedNeverProduced("pipe".to_string()), ProducedNeverUsed("engine".to_string())]);
+    }
+
+    #[test]
+    fn checks_long_chains() {
+        let chain: Vec<_> = (0..20_000).map(|i| recipe(&format!("x{}", i + 1), &[&format!("x{}", i)])).collect();
+        let found = issues(&chain);
+        assert_eq!(found, vec![ConsumedNeverProduced("x0".to_string()), ProducedNeverUsed("x20000".to_string())]);
+    }
+}
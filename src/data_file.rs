@@ -0,0 +1,650 @@
+//! Recipe data file, written by the fetcher and read by the planner.
+//!
+//! Format (version 8) is line based, every line is a list of tab separated
+//! fields, lines starting with `#` are comments. The first line that is not a
+//! comment is the version header `format<TAB>8`. It may be followed by the
+//! versions of the game and of the mods the data comes from:
+//!
+//! ```text
+//! game <version>
+//! mod  <name> <version>
+//! ```
+//!
+//! and then by recipe blocks:
+//!
+//! ```text
+//! recipe          <name>
+//! category        <category>
+//! enabled         true|false
+//! energy_required <seconds>
+//! ingredient      <type> <name> <amount> [<key>=<value>...]
+//! product         <type> <name> <amount> [<key>=<value>...]
+//! end
+//! ```
+//!
+//! Optional component attributes are `amount_min`, `amount_max`, `probability`,
+//! `temperature`, `minimum_temperature` and `maximum_temperature`.
+//!
+//! Recipes from Factorio 2.0 may have more optional lines (`-` is an open bound):
+//!
+//! ```text
+//! allow_productivity   true|false
+//! maximum_productivity <bonus>
+//! surface_condition    <property> <min>|- <max>|-
+//! ```
+//!
+//! and machine blocks with stats of crafting machines, mining drills and labs
+//! (all lines but `machine`, `type` and `end` are optional):
+//!
+//! ```text
+//! machine           <name>
+//! type              <prototype type>
+//! crafting_speed    <speed>
+//! crafting_category <category>      (one line per category)
+//! ingredient_count  <count>
+//! researching_speed <speed>
+//! mining_speed      <speed>
+//! energy_usage      <energy, e.g. 150kW>
+//! end
+//! ```
+//!
+//! and item blocks of items with properties the planner uses (spoilage and
+//! rocket weight):
+//!
+//! ```text
+//! item         <name>
+//! type         <prototype type>
+//! stack_size   <count>
+//! spoil_ticks  <ticks>
+//! spoil_result <item>
+//! weight       <weight>
+//! end
+//! ```
+//!
+//! and technology blocks of technologies with a fixed unit count:
+//!
+//! ```text
+//! technology   <name>
+//! unit_count   <count>
+//! unit_time    <seconds>
+//! ingredient   <type> <name> <amount>  (one line per science pack)
+//! prerequisite <name>                  (one line per prerequisite)
+//! end
+//! ```
+//!
+//! Older versions are read as well, they just lack what later versions added:
+//!
+//! * version 2: recipe blocks,
+//! * version 3: machine blocks,
+//! * version 4: `game` and `mod` lines,
+//! * version 5: the Factorio 2.0 recipe lines,
+//! * version 6: item blocks,
+//! * version 7: the item `weight` line,
+//! * version 8: technology blocks.
+//!
+//! Files without the version header are the legacy (version 1) tab separated
+//! format with one line per product: `product<TAB>amount<TAB>time<TAB>"a x b | c x d"`.
+//! They are converted on read, so `write_recipes(read_recipes(old))` migrates them.
+//! They have no categories, every recipe is read as `crafting`.
+
+use std::io;
+use std::io::prelude::*;
+use std::str::FromStr;
+use structs::{Entity, Item, Recipe, RecipeComponent, SurfaceCondition, Technology};
+
+pub const FORMAT_VERSION: u32 = 8;
+
+/// Versions of the game and of the mods a data file was fetched from.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stamp {
+    pub game_version: Option<String>,
+    pub mods: Vec<(String, String)>
+}
+
+impl Stamp {
+    /// E.g. `2.0.55 + space-age 2.0.55`, `unknown version` for unstamped data.
+    pub fn describe(&self) -> String {
+        let mut s = self.game_version.clone().unwrap_or("unknown version".to_string());
+        for &(ref name, ref version) in &self.mods {
+            s.push_str(&format!(" + {} {}", name, version));
+        }
+        s
+    }
+}
+
+/// Everything stored in a data file.
+#[derive(Debug, Default)]
+pub struct DataSet {
+    pub stamp: Stamp,
+    pub recipes: Vec<Recipe>,
+    pub machines: Vec<Entity>,
+    pub items: Vec<Item>,
+    pub technologies: Vec<Technology>
+}
+
+fn error(line: usize, message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line + 1, message))
+}
+
+fn parse_float(line: usize, s: &str) -> Result<f64, io::Error> {
+    f64::from_str(s.trim()).map_err(|why| error(line, format!("not a number [{}]: {}", s, why)))
+}
+
+fn parse_bool(line: usize, s: &str) -> Result<bool, io::Error> {
+    match s.trim() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(error(line, format!("not true or false [{}]", s)))
+    }
+}
+
+pub fn write_recipes<W: Write>(file: &mut W, recipes: &[Recipe]) -> Result<(), io::Error> {
+    write_header(file, &Stamp::default())?;
+    write_recipe_blocks(file, recipes)
+}
+
+pub fn write_data<W: Write>(file: &mut W, data: &DataSet) -> Result<(), io::Error> {
+    write_header(file, &data.stamp)?;
+    write_recipe_blocks(file, &data.recipes)?;
+    for machine in &data.machines {
+        write_machine(file, machine)?;
+    }
+    for item in &data.items {
+        write_item(file, item)?;
+    }
+    for technology in &data.technologies {
+        write_technology(file, technology)?;
+    }
+    Ok(())
+}
+
+fn write_header<W: Write>(file: &mut W, stamp: &Stamp) -> Result<(), io::Error> {
+    writeln!(file, "# factorio-recipes-planner data file")?;
+    writeln!(file, "format\t{}", FORMAT_VERSION)?;
+    if let Some(ref version) = stamp.game_version {
+        writeln!(file, "game\t{}", version)?;
+    }
+    for &(ref name, ref version) in &stamp.mods {
+        writeln!(file, "mod\t{}\t{}", name, version)?;
+    }
+    Ok(())
+}
+
+fn write_recipe_blocks<W: Write>(file: &mut W, recipes: &[Recipe]) -> Result<(), io::Error> {
+    for recipe in recipes {
+        writeln!(file, "recipe\t{}", recipe.name)?;
+        writeln!(file, "category\t{}", recipe.category)?;
+        writeln!(file, "enabled\t{}", recipe.enabled)?;
+        writeln!(file, "energy_required\t{}", recipe.energy_required)?;
+        for comp in &recipe.ingredients {
+            write_component(file, "ingredient", comp)?;
+        }
+        for comp in &recipe.products {
+            write_component(file, "product", comp)?;
+        }
+        if recipe.allow_productivity {
+            writeln!(file, "allow_productivity\ttrue")?;
+        }
+        if let Some(x) = recipe.maximum_productivity {
+            writeln!(file, "maximum_productivity\t{}", x)?;
+        }
+        for c in &recipe.surface_conditions {
+            writeln!(file, "surface_condition\t{}\t{}\t{}", c.property, bound(c.min), bound(c.max))?;
+        }
+        writeln!(file, "end")?;
+    }
+    Ok(())
+}
+
+fn bound(x: Option<f64>) -> String {
+    x.map(|x| x.to_string()).unwrap_or("-".to_string())
+}
+
+fn read_bound(line: usize, s: Option<&&str>) -> Result<Option<f64>, io::Error> {
+    match s {
+        Some(&"-") => Ok(None),
+        Some(s) => parse_float(line, s).map(Some),
+        None => Err(error(line, "no surface condition bound".to_string()))
+    }
+}
+
+fn write_machine<W: Write>(file: &mut W, machine: &Entity) -> Result<(), io::Error> {
+    writeln!(file, "machine\t{}", machine.name)?;
+    writeln!(file, "type\t{}", machine.a_type)?;
+    if let Some(x) = machine.crafting_speed {
+        writeln!(file, "crafting_speed\t{}", x)?;
+    }
+    for category in &machine.crafting_categories {
+        writeln!(file, "crafting_category\t{}", category)?;
+    }
+    let stats = [
+        ("ingredient_count", machine.ingredient_count),
+        ("researching_speed", machine.researching_speed),
+        ("mining_speed", machine.mining_speed)
+    ];
+    for &(key, value) in stats.iter() {
+        if let Some(value) = value {
+            writeln!(file, "{}\t{}", key, value)?;
+        }
+    }
+    if let Some(ref x) = machine.energy_usage {
+        writeln!(file, "energy_usage\t{}", x)?;
+    }
+    writeln!(file, "end")
+}
+
+fn write_item<W: Write>(file: &mut W, item: &Item) -> Result<(), io::Error> {
+    writeln!(file, "item\t{}", item.name)?;
+    writeln!(file, "type\t{}", item.a_type)?;
+    writeln!(file, "stack_size\t{}", item.stack_size)?;
+    if let Some(x) = item.spoil_ticks {
+        writeln!(file, "spoil_ticks\t{}", x)?;
+    }
+    if let Some(ref x) = item.spoil_result {
+        writeln!(file, "spoil_result\t{}", x)?;
+    }
+    if let Some(x) = item.weight {
+        writeln!(file, "weight\t{}", x)?;
+    }
+    writeln!(file, "end")
+}
+
+fn write_technology<W: Write>(file: &mut W, technology: &Technology) -> Result<(), io::Error> {
+    writeln!(file, "technology\t{}", technology.name)?;
+    writeln!(file, "unit_count\t{}", technology.unit_count)?;
+    writeln!(file, "unit_time\t{}", technology.unit_time)?;
+    for comp in &technology.unit_ingredients {
+        write_component(file, "ingredient", comp)?;
+    }
+    for prerequisite in &technology.prerequisites {
+        writeln!(file, "prerequisite\t{}", prerequisite)?;
+    }
+    writeln!(file, "end")
+}
+
+fn write_component<W: Write>(file: &mut W, kind: &str, comp: &RecipeComponent) -> Result<(), io::Error> {
+    write!(file, "{}\t{}\t{}\t{}", kind, comp.a_type, comp.name, comp.amount)?;
+    let attributes = [
+        ("amount_min", comp.amount_min),
+        ("amount_max", comp.amount_max),
+        ("probability", if comp.probability != 1.0 { Some(comp.probability) } else { None }),
+        ("temperature", comp.temperature),
+        ("minimum_temperature", comp.minimum_temperature),
+        ("maximum_temperature", comp.maximum_temperature)
+    ];
+    for &(key, value) in attributes.iter() {
+        if let Some(value) = value {
+            write!(file, "\t{}={}", key, value)?;
+        }
+    }
+    writeln!(file)
+}
+
+/// Reads recipes from a data file of any supported version.
+pub fn read_recipes(s: &str) -> Result<Vec<Recipe>, io::Error> {
+    read_data(s).map(|data| data.recipes)
+}
+
+/// First line that is not a comment.
+fn header(s: &str) -> Option<&str> {
+    s.lines().map(|x| x.trim()).find(|x| !x.is_empty() && !x.starts_with("#"))
+}
+
+/// Whether `s` is a legacy file (version 1), which has no recipe categories.
+pub fn is_legacy(s: &str) -> bool {
+    header(s).is_some_and(|x| !x.starts_with("format"))
+}
+
+/// Reads recipes, machines, items and technologies from a data file of any
+/// supported version.
+pub fn read_data(s: &str) -> Result<DataSet, io::Error> {
+    match header(s) {
+        None => Ok(DataSet::default()),
+        Some(header) if header.starts_with("format") => {
+            let version = header["format".len()..].trim();
+            match u32::from_str(version) {
+                Ok(v) if (2..=FORMAT_VERSION).contains(&v) => read_data_v8(s),
+                _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported data format version: {}", version)))
+            }
+        },
+        Some(_) => read_recipes_v1(s).map(|recipes| DataSet { recipes: recipes, ..DataSet::default() })
+    }
+}
+
+/// Reads versions 2 to 8, older versions just lack some lines.
+fn read_data_v8(s: &str) -> Result<DataSet, io::Error> {
+    let mut stamp = Stamp::default();
+    let mut recipes = Vec::new();
+    let mut machines = Vec::new();
+    let mut items = Vec::new();
+    let mut technologies = Vec::new();
+    let mut current: Option<Recipe> = None;
+    let mut machine: Option<Entity> = None;
+    let mut item: Option<Item> = None;
+    let mut technology: Option<Technology> = None;
+    for (i, line) in s.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with("#") {
+            continue;
+        }
+        let parts: Vec<_> = line.split("\t").collect();
+        let value = || parts.get(1).copied().ok_or(error(i, format!("no value: {}", line)));
+        let in_block = current.is_some() || machine.is_some() || item.is_some() || technology.is_some();
+        match parts[0] {
+            "format" => (),
+            "game" if !in_block => stamp.game_version = Some(value()?.to_string()),
+            "mod" if !in_block => match parts.get(2) {
+                Some(version) => stamp.mods.push((value()?.to_string(), version.to_string())),
+                None => return Err(error(i, format!("no mod version: {}", line)))
+            },
+            "recipe" | "machine" | "item" | "technology" if in_block => {
+                return Err(error(i, "block without \"end\"".to_string()));
+            },
+            _ if parts[0] != "end" && technology.is_some() => {
+                let t = technology.as_mut().unwrap();
+                match parts[0] {
+                    "unit_count" => t.unit_count = parse_float(i, value()?)?,
+                    "unit_time" => t.unit_time = parse_float(i, value()?)?,
+                    "ingredient" => t.unit_ingredients.push(read_component(i, &parts)?),
+                    "prerequisite" => t.prerequisites.push(value()?.to_string()),
+                    key => return Err(error(i, format!("unknown key: {}", key)))
+                }
+            },
+            _ if parts[0] != "end" && item.is_some() => {
+                let it = item.as_mut().unwrap();
+                match parts[0] {
+                    "type" => it.a_type = value()?.to_string(),
+                    "stack_size" => it.stack_size = parse_float(i, value()?)?,
+                    "spoil_ticks" => it.spoil_ticks = Some(parse_float(i, value()?)?),
+                    "spoil_result" => it.spoil_result = Some(value()?.to_string()),
+                    "weight" => it.weight = Some(parse_float(i, value()?)?),
+                    key => return Err(error(i, format!("unknown key: {}", key)))
+                }
+            },
+            "technology" => {
+                technology = Some(Technology {
+                    name: value()?.to_string(),
+                    unit_count: 1.0,
+                    unit_time: 1.0,
+                    unit_ingredients: Vec::new(),
+                    prerequisites: Vec::new()
+                });
+            },
+            "item" => {
+                item = Some(Item {
+                    a_type: "item".to_string(),
+                    name: value()?.to_string(),
+                    stack_size: 1.0,
+                    subgroup: None,
+                    place_result: None,
+                    fuel_value: None,
+                    spoil_ticks: None,
+                    spoil_result: None,
+                    weight: None
+                });
+            },
+            "machine" => {
+                machine = Some(Entity {
+                    a_type: String::new(),
+                    name: value()?.to_string(),
+                    crafting_speed: None,
+                    crafting_categories: Vec::new(),
+                    ingredient_count: None,
+                    researching_speed: None,
+                    mining_speed: None,
+                    energy_usage: None
+                });
+            },
+            "recipe" => {
+                current = Some(Recipe {
+                    name: value()?.to_string(),
+                    category: "crafting".to_string(),
+                    products: Vec::new(),
+                    ingredients: Vec::new(),
+                    energy_required: 0.5,
+                    enabled: false,
+                    allow_productivity: false,
+                    maximum_productivity: None,
+                    surface_conditions: Vec::new()
+                });
+            },
+            "end" => match (current.take(), machine.take(), item.take(), technology.take()) {
+                (Some(recipe), _, _, _) => recipes.push(recipe),
+                (None, Some(m), _, _) => machines.push(m),
+                (None, None, Some(it), _) => items.push(it),
+                (None, None, None, Some(t)) => technologies.push(t),
+                (None, None, None, None) => return Err(error(i, "\"end\" outside of block".to_string()))
+            },
+            key if machine.is_some() => {
+                let m = machine.as_mut().unwrap();
+                match key {
+                    "type" => m.a_type = value()?.to_string(),
+                    "crafting_speed" => m.crafting_speed = Some(parse_float(i, value()?)?),
+                    "crafting_category" => m.crafting_categories.push(value()?.to_string()),
+                    "ingredient_count" => m.ingredient_count = Some(parse_float(i, value()?)?),
+                    "researching_speed" => m.researching_speed = Some(parse_float(i, value()?)?),
+                    "mining_speed" => m.mining_speed = Some(parse_float(i, value()?)?),
+                    "energy_usage" => m.energy_usage = Some(value()?.to_string()),
+                    _ => return Err(error(i, format!("unknown key: {}", key)))
+                }
+            },
+            key => {
+                let recipe = match current.as_mut() {
+                    Some(recipe) => recipe,
+                    None => return Err(error(i, format!("\"{}\" outside of recipe", key)))
+                };
+                match key {
+                    "category" => recipe.category = value()?.to_string(),
+                    "enabled" => recipe.enabled = parse_bool(i, value()?)?,
+                    "energy_required" => recipe.energy_required = parse_float(i, value()?)?,
+                    "ingredient" => recipe.ingredients.push(read_component(i, &parts)?),
+                    "product" => recipe.products.push(read_component(i, &parts)?),
+                    "allow_productivity" => recipe.allow_productivity = parse_bool(i, value()?)?,
+                    "maximum_productivity" => recipe.maximum_productivity = Some(parse_float(i, value()?)?),
+                    "surface_condition" => recipe.surface_conditions.push(SurfaceCondition {
+                        property: value()?.to_string(),
+                        min: read_bound(i, parts.get(2))?,
+                        max: read_bound(i, parts.get(3))?
+                    }),
+                    _ => return Err(error(i, format!("unknown key: {}", key)))
+                }
+            }
+        }
+    }
+    if current.is_some() || machine.is_some() || item.is_some() || technology.is_some() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "block without \"end\""));
+    }
+    Ok(DataSet { stamp: stamp, recipes: recipes, machines: machines, items: items, technologies: technologies })
+}
+
+fn read_component(line: usize, parts: &[&str]) -> Result<RecipeComponent, io::Error> {
+    if parts.len() < 4 {
+        return Err(error(line, format!("not enough parts: {} of 4", parts.len())));
+    }
+    let mut comp = RecipeComponent {
+        a_type: parts[1].to_string(),
+        name: parts[2].to_string(),
+        amount: parse_float(line, parts[3])?,
+        amount_min: None,
+        amount_max: None,
+        probability: 1.0,
+        temperature: None,
+        minimum_temperature: None,
+        maximum_temperature: None
+    };
+    for attribute in &parts[4..] {
+        let (key, value) = match attribute.find("=") {
+            Some(j) => (&attribute[..j], parse_float(line, &attribute[j + 1..])?),
+            None => return Err(error(line, format!("attribute without value: {}", attribute)))
+        };
+        match key {
+            "amount_min" => comp.amount_min = Some(value),
+            "amount_max" => comp.amount_max = Some(value),
+            "probability" => comp.probability = value,
+            "temperature" => comp.temperature = Some(value),
+            "minimum_temperature" => comp.minimum_temperature = Some(value),
+            "maximum_temperature" => comp.maximum_temperature = Some(value),
+            _ => return Err(error(line, format!("unknown attribute: {}", key)))
+        }
+    }
+    Ok(comp)
+}
+
+/// Parses legacy component `type:name@min..max` (type and temperature are optional).
+fn read_legacy_component(line: usize, s: &str, amount: f64) -> Result<RecipeComponent, io::Error> {
+    let (a_type, rest) = match s.find(":") {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => ("item", s)
+    };
+    let mut comp = RecipeComponent {
+        a_type: a_type.to_string(),
+        name: rest.to_string(),
+        amount: amount,
+        amount_min: None,
+        amount_max: None,
+        probability: 1.0,
+        temperature: None,
+        minimum_temperature: None,
+        maximum_temperature: None
+    };
+    if let Some(i) = rest.find("@") {
+        comp.name = rest[..i].to_string();
+        let t = &rest[i + 1..];
+        let bound = |x: &str| if x.is_empty() { Ok(None) } else { parse_float(line, x).map(Some) };
+        match t.find("..") {
+            Some(j) => {
+                comp.minimum_temperature = bound(&t[..j])?;
+                comp.maximum_temperature = bound(&t[j + 2..])?;
+            },
+            None => comp.temperature = bound(t)?
+        }
+    }
+    Ok(comp)
+}
+
+fn read_recipes_v1(s: &str) -> Result<Vec<Recipe>, io::Error> {
+    let mut recipes = Vec::new();
+    for (i, line) in s.lines().enumerate() {
+        if line.starts_with("#") || line.trim().is_empty() {
+            continue;
+        }
+        let parts: Vec<_> = line.split("\t").collect();
+        if parts.len() < 4 {
+            return Err(error(i, format!("not enough parts: {} of 4", parts.len())));
+        }
+        let mut ingredients = Vec::new();
+        for s in parts[3].replace("\"", "").split("|") {
+            let v: Vec<_> = s.splitn(2, " x ").collect();
+            if v.len() < 2 {
+                return Err(error(i, format!("illegal component: {}", s)));
+            }
+            ingredients.push(read_legacy_component(i, v[1].trim(), parse_float(i, v[0])?)?);
+        }
+        let mut product = read_legacy_component(i, parts[0], 1.0)?;
+        let amount = match parts[1].find("*") {
+            Some(j) => {
+                product.probability = parse_float(i, &parts[1][j + 1..])?;
+                &parts[1][..j]
+            },
+            None => parts[1]
+        };
+        match amount.find("..") {
+            Some(j) => {
+                let (min, max) = (parse_float(i, &amount[..j])?, parse_float(i, &amount[j + 2..])?);
+                product.amount_min = Some(min);
+                product.amount_max = Some(max);
+                product.amount = (min + max) / 2.0;
+            },
+            None => product.amount = parse_float(i, amount)?
+        }
+        recipes.push(Recipe {
+            name: product.name.clone(),
+            category: "crafting".to_string(),
+            products: vec![product],
+            ingredients: ingredients,
+            energy_required: parse_float(i, parts[2])?,
+            enabled: false,
+            allow_productivity: false,
+            maximum_productivity: None,
+            surface_conditions: Vec::new()
+        });
+    }
+    Ok(recipes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGACY: &'static str = "# old planner data\n\
+        electronic-circuit\t1\t0.5\t\"1 x iron-plate | 3 x copper-cable\"\n\
+        uranium-235\t1*0.007\t12\t\"10 x uranium-ore\"\n\
+        stone\t1..3\t1\t\"1 x fluid:water@15..90\"\n\
+        steam\t60\t1\t\"6 x fluid:water\"\n";
+
+    #[test]
+    fn migrates_legacy_files() {
+        assert!(is_legacy(LEGACY));
+        assert!(!is_legacy(&format!("# comment\nformat\t{}\n", FORMAT_VERSION)));
+        let recipes = read_recipes(LEGACY).unwrap();
+        assert_eq!(recipes.len(), 4);
+        let circuit = &recipes[0];
+        assert_eq!(circuit.name, "electronic-circuit");
+        assert_eq!(circuit.category, "crafting");
+        assert_eq!(circuit.energy_required, 0.5);
+        assert_eq!(circuit.ingredients.iter().map(|x| (&x.name[..], x.amount)).collect::<Vec<_>>(),
+            vec![("iron-plate", 1.0), ("copper-cable", 3.0)]);
+        assert_eq!(recipes[1].products[0].probability, 0.007);
+        assert_eq!((recipes[2].products[0].amount_min, recipes[2].products[0].amount_max), (Some(1.0), Some(3.0)));
+        let water = &recipes[2].ingredients[0];
+        assert_eq!((&water.a_type[..], water.minimum_temperature, water.maximum_temperature), ("fluid", Some(15.0), Some(90.0)));
+
+        let mut out = Vec::new();
+        write_recipes(&mut out, &recipes).unwrap();
+        let migrated = String::from_utf8(out).unwrap();
+        assert!(migrated.contains(&format!("\nformat\t{}\n", FORMAT_VERSION)));
+        assert_eq!(read_recipes(&migrated).unwrap(), recipes);
+    }
+
+    #[test]
+    fn reads_version_lines_only_outside_of_blocks() {
+        let data = read_data("format\t7\ngame\t2.0.55\nmod\tspace-age\t2.0.55\nitem\tice\ngame\t1.1\nend\n");
+        assert!(data.is_err());
+        let data = read_data("format\t7\ngame\t2.0.55\nmod\tspace-age\t2.0.55\nitem\tice\nweight\t1000\nend\n").unwrap();
+        assert_eq!(data.stamp.describe(), "2.0.55 + space-age 2.0.55");
+        assert_eq!(data.items[0].weight, Some(1000.0));
+    }
+
+    #[test]
+    fn reads_technology_blocks() {
+        let text = "format\t8\ntechnology\tautomation-2\nunit_count\t40\nunit_time\t5\n\
+            ingredient\titem\tautomation-science-pack\t1\ningredient\titem\tlogistic-science-pack\t1\n\
+            prerequisite\tautomation\nprerequisite\tlogistic-science-pack\nend\n";
+        let data = read_data(text).unwrap();
+        let technology = &data.technologies[0];
+        assert_eq!((&technology.name[..], technology.unit_count, technology.unit_time), ("automation-2", 40.0, 5.0));
+        assert_eq!(technology.unit_ingredients.iter().map(|x| &x.name[..]).collect::<Vec<_>>(),
+            vec!["automation-science-pack", "logistic-science-pack"]);
+        assert_eq!(technology.prerequisites, vec!["automation", "logistic-science-pack"]);
+
+        let mut out = Vec::new();
+        write_data(&mut out, &data).unwrap();
+        assert_eq!(read_data(&String::from_utf8(out).unwrap()).unwrap().technologies, data.technologies);
+        assert!(read_data("format\t8\ntechnology\ta\nproduct\titem\tb\t1\nend\n").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_versions_and_unfinished_blocks() {
+        assert!(read_data(&format!("format\t{}\n", FORMAT_VERSION + 1)).is_err());
+        assert!(read_data("format\t7\nrecipe\ta\n").is_err());
+        assert!(read_data("format\t7\nend\n").is_err());
+    }
+
+    #[test]
+    fn reads_only_true_or_false_flags() {
+        let recipes = read_recipes("format\t8\nrecipe\ta\nenabled\ttrue\nallow_productivity\tfalse\nend\n").unwrap();
+        assert_eq!((recipes[0].enabled, recipes[0].allow_productivity), (true, false));
+        let error = read_recipes("format\t8\nrecipe\ta\nenabled\tyes\nend\n").err().unwrap();
+        assert_eq!(error.to_string(), "line 3: not true or false [yes]");
+        assert!(read_recipes("format\t8\nrecipe\ta\nallow_productivity\t1\nend\n").is_err());
+    }
+}
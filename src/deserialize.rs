@@ -0,0 +1,219 @@
+//! Deserialization of parsed Lua values into typed Rust structs.
+//!
+//! Structs get a `FromLua` implementation with the `impl_from_lua!` macro,
+//! which maps every struct field to a Lua key, optionally with a default:
+//!
+//! ```ignore
+//! impl_from_lua!(TechnologyUnit {
+//!     count: "count" => None,
+//!     time: "time",
+//!     ingredients: "ingredients"
+//! });
+//! ```
+//!
+//! Fields without a default are required, unless their type has a natural
+//! "missing" value (`Option<T>` is `None`, `Vec<T>` is empty).
+
+use std::fmt;
+use lua::{LuaObject, LuaField};
+use lua::LuaField::*;
+
+/// Deserialization error with the path to the offending value,
+/// e.g. `recipe.rocket-part.ingredients[2].amount`.
+#[derive(Debug)]
+pub struct LuaError {
+    pub path: String,
+    pub message: String
+}
+
+impl fmt::Display for LuaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl LuaError {
+    pub fn new(path: &str, message: String) -> LuaError {
+        LuaError { path: path.to_string(), message: message }
+    }
+
+    fn unexpected(path: &str, expected: &str, field: &LuaField) -> LuaError {
+        LuaError::new(path, format!("expected {}, found {}", expected, describe(field)))
+    }
+}
+
+fn describe(field: &LuaField) -> String {
+    match *field {
+        AString(ref s) => format!("string \"{}\"", s),
+        AFloat(x) => format!("number {}", x),
+        ABool(x) => format!("boolean {}", x),
+        AnObject(_) => "table".to_string()
+    }
+}
+
+pub trait FromLua: Sized {
+    fn from_lua(field: &LuaField, path: &str) -> Result<Self, LuaError>;
+
+    /// Value of a missing field, `None` if the field is required.
+    fn missing() -> Option<Self> {
+        None
+    }
+}
+
+impl FromLua for String {
+    fn from_lua(field: &LuaField, path: &str) -> Result<String, LuaError> {
+        match *field {
+            AString(ref s) => Ok(s.to_string()),
+            _ => Err(LuaError::unexpected(path, "string", field))
+        }
+    }
+}
+
+impl FromLua for f64 {
+    fn from_lua(field: &LuaField, path: &str) -> Result<f64, LuaError> {
+        match *field {
+            AFloat(x) => Ok(x),
+            _ => Err(LuaError::unexpected(path, "number", field))
+        }
+    }
+}
+
+/// Booleans are also accepted as strings "true" and "false".
+impl FromLua for bool {
+    fn from_lua(field: &LuaField, path: &str) -> Result<bool, LuaError> {
+        match *field {
+            ABool(x) => Ok(x),
+            AString(ref s) if s == "true" => Ok(true),
+            AString(ref s) if s == "false" => Ok(false),
+            _ => Err(LuaError::unexpected(path, "boolean", field))
+        }
+    }
+}
+
+/// Tables are copied out of the source buffer.
+impl FromLua for LuaObject<'static> {
+    fn from_lua(field: &LuaField, path: &str) -> Result<LuaObject<'static>, LuaError> {
+        match *field {
+            AnObject(ref obj) => Ok((**obj).clone().into_owned()),
+            _ => Err(LuaError::unexpected(path, "table", field))
+        }
+    }
+}
+
+impl<T: FromLua> FromLua for Option<T> {
+    fn from_lua(field: &LuaField, path: &str) -> Result<Option<T>, LuaError> {
+        T::from_lua(field, path).map(Some)
+    }
+
+    fn missing() -> Option<Option<T>> {
+        Some(None)
+    }
+}
+
+/// Arrays are Lua tables with unnamed fields, indexed from 1 in error paths.
+impl<T: FromLua> FromLua for Vec<T> {
+    fn from_lua(field: &LuaField, path: &str) -> Result<Vec<T>, LuaError> {
+        match *field {
+            AnObject(ref obj) => {
+                let mut vec = Vec::new();
+                for (i, &(_, ref field)) in obj.fields.iter().enumerate() {
+                    vec.push(T::from_lua(field, &format!("{}[{}]", path, i + 1))?);
+                }
+                Ok(vec)
+            },
+            _ => Err(LuaError::unexpected(path, "table", field))
+        }
+    }
+
+    fn missing() -> Option<Vec<T>> {
+        Some(Vec::new())
+    }
+}
+
+/// Deserializes the named field `key` of `obj`, falling back to `default`
+/// and then to `T::missing()` if the field is absent.
+pub fn field<T: FromLua>(obj: &LuaObject, key: &str, path: &str, default: Option<T>) -> Result<T, LuaError> {
+    let path = format!("{}.{}", path, key);
+    match obj.get(key) {
+        Some(field) => T::from_lua(field, &path),
+        None => match default.or_else(T::missing) {
+            Some(x) => Ok(x),
+            None => Err(LuaError::new(&path, "missing field".to_string()))
+        }
+    }
+}
+
+/// Returns the table of `field`, or an error if it is not a table.
+pub fn as_object<'a, 's>(field: &'a LuaField<'s>, path: &str) -> Result<&'a LuaObject<'s>, LuaError> {
+    match *field {
+        AnObject(ref obj) => Ok(obj),
+        _ => Err(LuaError::unexpected(path, "table", field))
+    }
+}
+
+#[macro_export]
+macro_rules! lua_default {
+    () => { None };
+    ($default:expr) => { Some($default) };
+}
+
+#[macro_export]
+macro_rules! impl_from_lua {
+    ($name:ident { $($field:ident : $key:expr $(=> $default:expr)*),* }) => {
+        impl $crate::deserialize::FromLua for $name {
+            fn from_lua(field: &$crate::lua::LuaField, path: &str) -> Result<$name, $crate::deserialize::LuaError> {
+                let obj = $crate::deserialize::as_object(field, path)?;
+                Ok($name {
+                    $($field: $crate::deserialize::field(obj, $key, path, lua_default!($($default)*))?),*
+                })
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use lua;
+    use super::*;
+
+    struct Unit {
+        count: Option<f64>,
+        time: f64,
+        name: String,
+        enabled: bool,
+        ingredients: Vec<String>
+    }
+
+    impl_from_lua!(Unit {
+        count: "count",
+        time: "time" => 30.0,
+        name: "name",
+        enabled: "enabled" => true,
+        ingredients: "ingredients"
+    });
+
+    fn unit(src: &str) -> Result<Unit, LuaError> {
+        let obj = lua::parse_file(&format!("data:extend({{{}}})", src)).unwrap().remove(0).into_owned();
+        Unit::from_lua(&AnObject(Box::new(obj)), "unit")
+    }
+
+    #[test]
+    fn fills_in_optional_fields() {
+        let x = unit("{name = 'a'}").unwrap();
+        assert_eq!((x.count, x.time, &x.name[..], x.enabled, x.ingredients.len()), (None, 30.0, "a", true, 0));
+        let x = unit("{name = 'a', count = 2, time = 5, enabled = 'false', ingredients = {'b', 'c'}}").unwrap();
+        assert_eq!((x.count, x.time, x.enabled, x.ingredients), (Some(2.0), 5.0, false, vec!["b".to_string(), "c".to_string()]));
+    }
+
+    #[test]
+    fn reports_missing_fields_and_wrong_types_with_their_path() {
+        let error = unit("{count = 1}").err().unwrap();
+        assert_eq!(error.to_string(), "unit.name: missing field");
+        let error = unit("{name = 'a', time = 'long'}").err().unwrap();
+        assert_eq!(error.to_string(), "unit.time: expected number, found string \"long\"");
+        let error = unit("{name = 'a', ingredients = {'b', 2}}").err().unwrap();
+        assert_eq!(error.to_string(), "unit.ingredients[2]: expected string, found number 2");
+        let error = unit("{name = 'a', count = {}}").err().unwrap();
+        assert_eq!(error.to_string(), "unit.count: expected number, found table");
+    }
+}
@@ -0,0 +1,241 @@
+//! Differences between two data sets, e.g. before and after a game or mod update.
+
+use std::collections::{HashMap, HashSet};
+use data_file::DataSet;
+use structs::{Entity, Item, Recipe, RecipeComponent, SurfaceCondition};
+use preferences::MachinePreferences;
+use rational::Rational;
+use solver::{build_dependency_net, make_plan, to_product_recipes_with};
+
+/// Changes of a single recipe or machine, e.g. `energy_required: 0.5 -> 1`.
+#[derive(Debug)]
+pub struct Changes {
+    pub name: String,
+    pub changes: Vec<String>
+}
+
+#[derive(Debug, Default)]
+pub struct SectionDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<Changes>
+}
+
+impl SectionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct DataDiff {
+    pub recipes: SectionDiff,
+    pub machines: SectionDiff,
+    pub items: SectionDiff
+}
+
+/// Machine count of a plan step in the old and the new data set
+/// (`None` if the step is not in the plan).
+#[derive(Debug)]
+pub struct CountChange {
+    pub name: String,
+    pub old: Option<Rational>,
+    pub new: Option<Rational>
+}
+
+pub fn diff(old: &DataSet, new: &DataSet) -> DataDiff {
+    DataDiff {
+        recipes: diff_section(&old.recipes, &new.recipes, |x| &x.name, compare_recipes),
+        machines: diff_section(&old.machines, &new.machines, |x| &x.name, compare_machines),
+        items: diff_section(&old.items, &new.items, |x| &x.name, compare_items)
+    }
+}
+
+fn diff_section<T, N, C>(old: &[T], new: &[T], name: N, compare: C) -> SectionDiff
+    where N: Fn(&T) -> &str, C: Fn(&T, &T) -> Vec<String>
+{
+    let old_map: HashMap<_, _> = old.iter().map(|x| (name(x), x)).collect();
+    let new_map: HashMap<_, _> = new.iter().map(|x| (name(x), x)).collect();
+    let mut diff = SectionDiff::default();
+    for (n, a) in &old_map {
+        match new_map.get(n) {
+            None => diff.removed.push(n.to_string()),
+            Some(b) => {
+                let changes = compare(a, b);
+                if !changes.is_empty() {
+                    diff.changed.push(Changes { name: n.to_string(), changes: changes });
+                }
+            }
+        }
+    }
+    diff.added = new_map.keys().filter(|n| !old_map.contains_key(*n)).map(|n| n.to_string()).collect();
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed.sort_by(|a, b| a.name.cmp(&b.name));
+    diff
+}
+
+fn change<T: PartialEq + ToString>(out: &mut Vec<String>, key: &str, old: T, new: T) {
+    if old != new {
+        out.push(format!("{}: {} -> {}", key, old.to_string(), new.to_string()));
+    }
+}
+
+fn optional(x: Option<f64>) -> String {
+    x.map(|x| x.to_string()).unwrap_or("-".to_string())
+}
+
+/// Amount of a component with its attributes, e.g. `1..3 *0.5 @165`.
+fn describe(comp: &RecipeComponent) -> String {
+    let mut s = match (comp.amount_min, comp.amount_max) {
+        (Some(min), Some(max)) => format!("{}..{}", min, max),
+        _ => comp.amount.to_string()
+    };
+    if comp.probability != 1.0 {
+        s.push_str(&format!(" *{}", comp.probability));
+    }
+    match (comp.temperature, comp.minimum_temperature, comp.maximum_temperature) {
+        (Some(t), _, _) => s.push_str(&format!(" @{}", t)),
+        (None, None, None) => (),
+        (None, min, max) => s.push_str(&format!(" @{}..{}", optional(min), optional(max)))
+    }
+    s
+}
+
+/// Surface conditions, e.g. `pressure 1000..2000, gravity 0.1..-`.
+fn describe_conditions(conditions: &[SurfaceCondition]) -> String {
+    let list: Vec<_> = conditions.iter().map(|c| format!("{} {}..{}", c.property, optional(c.min), optional(c.max))).collect();
+    if list.is_empty() { "-".to_string() } else { list.join(", ") }
+}
+
+fn compare_components(out: &mut Vec<String>, kind: &str, old: &[RecipeComponent], new: &[RecipeComponent]) {
+    let find = |list: &[RecipeComponent], c: &RecipeComponent| list.iter()
+        .find(|x| x.name == c.name && x.a_type == c.a_type).map(describe);
+    for c in old {
+        match find(new, c) {
+            None => out.push(format!("{} {}: removed ({})", kind, c.name, describe(c))),
+            Some(d) => change(out, &format!("{} {}", kind, c.name), describe(c), d)
+        }
+    }
+    for c in new {
+        if find(old, c).is_none() {
+            out.push(format!("{} {}: added ({})", kind, c.name, describe(c)));
+        }
+    }
+}
+
+fn compare_recipes(a: &Recipe, b: &Recipe) -> Vec<String> {
+    let mut out = Vec::new();
+    change(&mut out, "category", &a.category, &b.category);
+    change(&mut out, "enabled", a.enabled, b.enabled);
+    change(&mut out, "energy_required", a.energy_required, b.energy_required);
+    change(&mut out, "allow_productivity", a.allow_productivity, b.allow_productivity);
+    change(&mut out, "maximum_productivity", optional(a.maximum_productivity), optional(b.maximum_productivity));
+    change(&mut out, "surface_conditions", describe_conditions(&a.surface_conditions), describe_conditions(&b.surface_conditions));
+    compare_components(&mut out, "ingredient", &a.ingredients, &b.ingredients);
+    compare_components(&mut out, "product", &a.products, &b.products);
+    out
+}
+
+fn compare_machines(a: &Entity, b: &Entity) -> Vec<String> {
+    let mut out = Vec::new();
+    change(&mut out, "type", &a.a_type, &b.a_type);
+    change(&mut out, "crafting_speed", optional(a.crafting_speed), optional(b.crafting_speed));
+    change(&mut out, "ingredient_count", optional(a.ingredient_count), optional(b.ingredient_count));
+    change(&mut out, "researching_speed", optional(a.researching_speed), optional(b.researching_speed));
+    change(&mut out, "mining_speed", optional(a.mining_speed), optional(b.mining_speed));
+    let energy = |x: &Entity| x.energy_usage.clone().unwrap_or("-".to_string());
+    change(&mut out, "energy_usage", energy(a), energy(b));
+    for category in &a.crafting_categories {
+        if !b.crafting_categories.contains(category) {
+            out.push(format!("crafting_category {}: removed", category));
+        }
+    }
+    for category in &b.crafting_categories {
+        if !a.crafting_categories.contains(category) {
+            out.push(format!("crafting_category {}: added", category));
+        }
+    }
+    out
+}
+
+fn compare_items(a: &Item, b: &Item) -> Vec<String> {
+    let mut out = Vec::new();
+    change(&mut out, "stack_size", a.stack_size, b.stack_size);
+    change(&mut out, "spoil_ticks", optional(a.spoil_ticks), optional(b.spoil_ticks));
+    let result = |x: &Item| x.spoil_result.clone().unwrap_or("-".to_string());
+    change(&mut out, "spoil_result", result(a), result(b));
+    change(&mut out, "weight", optional(a.weight), optional(b.weight));
+    out
+}
+
+fn machine_counts(recipes: &[Recipe], targets: &HashMap<String, Rational>, preferences: &MachinePreferences) -> HashMap<String, Rational> {
+    let m = to_product_recipes_with(recipes, preferences);
+    let graph = build_dependency_net(&m, targets.keys().cloned().collect());
+    make_plan(&m, targets, graph).out.into_iter()
+        .filter_map(|(name, p)| p.time.map(|time| (name, time * p.rate)))
+        .collect()
+}
+
+/// Runs the plan for `targets` (rates per second) on both data sets and
+/// returns the steps whose machine count changed.
+pub fn diff_plan(old: &DataSet, new: &DataSet, targets: &HashMap<String, Rational>, preferences: &MachinePreferences) -> Vec<CountChange> {
+    let old_counts = machine_counts(&old.recipes, targets, &preferences.clone().with_machines(&old.machines));
+    let new_counts = machine_counts(&new.recipes, targets, &preferences.clone().with_machines(&new.machines));
+    let names: HashSet<_> = old_counts.keys().chain(new_counts.keys()).collect();
+    let mut out: Vec<_> = names.into_iter()
+        .map(|name| CountChange { name: name.clone(), old: old_counts.get(name).cloned(), new: new_counts.get(name).cloned() })
+        .filter(|x| match (x.old, x.new) {
+            (Some(a), Some(b)) => a != b,
+            _ => true
+        })
+        .collect();
+    out.sort_by(|a, b| a.name.cmp(&b.name));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use data_file;
+    use super::*;
+
+    const OLD: &'static str = "format\t8\n\
+        recipe\tgear\nenergy_required\t0.5\ningredient\titem\tiron-plate\t2\nproduct\titem\tgear\t1\nend\n\
+        recipe\tcable\nenergy_required\t0.5\ningredient\titem\tcopper-plate\t1\nproduct\titem\tcable\t2\nend\n\
+        recipe\turanium-processing\ncategory\tcentrifuging\nenergy_required\t12\ningredient\titem\turanium-ore\t10\n\
+        product\titem\turanium-235\t1\tprobability=0.007\nproduct\titem\turanium-238\t1\tprobability=0.993\nend\n";
+
+    fn new_data() -> String {
+        OLD.replace("iron-plate\t2", "iron-plate\t4")
+            .replace("probability=0.007", "probability=0.008")
+            .replace("recipe\tcable\nenergy_required\t0.5\ningredient\titem\tcopper-plate\t1\nproduct\titem\tcable\t2\nend\n",
+                     "recipe\trod\nenergy_required\t1\ningredient\titem\tiron-plate\t1\nproduct\titem\trod\t2\nend\n")
+    }
+
+    #[test]
+    fn lists_added_removed_and_changed_recipes() {
+        let old = data_file::read_data(OLD).unwrap();
+        let new = data_file::read_data(&new_data()).unwrap();
+        let d = diff(&old, &new);
+        assert_eq!(d.recipes.added, vec!["rod"]);
+        assert_eq!(d.recipes.removed, vec!["cable"]);
+        let changed: Vec<_> = d.recipes.changed.iter().map(|x| (&x.name[..], x.changes.clone())).collect();
+        assert_eq!(changed, vec![
+            ("gear", vec!["ingredient iron-plate: 2 -> 4".to_string()]),
+            ("uranium-processing", vec!["product uranium-235: 1 *0.007 -> 1 *0.008".to_string()])
+        ]);
+        assert!(d.machines.is_empty() && d.items.is_empty());
+        assert!(diff(&old, &old).recipes.is_empty());
+    }
+
+    #[test]
+    fn compares_machine_counts_of_a_plan() {
+        let old = data_file::read_data(OLD).unwrap();
+        let new = data_file::read_data(&new_data()).unwrap();
+        let targets = vec![("gear".to_string(), Rational::ONE), ("cable".to_string(), Rational::ONE)].into_iter().collect();
+        let changes = diff_plan(&old, &new, &targets, &MachinePreferences::new());
+        // gear machines stay the same, the cable step is gone
+        let changes: Vec<_> = changes.iter().map(|x| (&x.name[..], x.old, x.new)).collect();
+        assert_eq!(changes, vec![("cable", Some(Rational::new(1, 2)), None)]);
+    }
+}
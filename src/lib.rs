@@ -0,0 +1,36 @@
+//! Planning tool for building factories in Factorio.
+//!
+//! The `fetcher` and `planner` binaries are thin front-ends over this crate:
+//! `lua` parses prototype files, `deserialize` and `prototypes` convert them
+//! to the data model from `structs` (which also holds the machine database),
+//! `data_file` stores recipes on disk, and `solver` builds production plans
+//! from them.
+
+// The code base keeps the explicit 2015 style (`name: name`, `&'static str`
+// in constants, `&(ref a, ref b)` patterns).
+#![allow(unknown_lints)]
+#![allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes, clippy::needless_borrowed_reference)]
+
+pub mod structs;
+pub mod rational;
+pub mod lua;
+pub mod lua_writer;
+#[macro_use]
+pub mod deserialize;
+pub mod prototypes;
+pub mod query;
+pub mod cache;
+pub mod data_file;
+pub mod builtin;
+pub mod mod_info;
+pub mod profiles;
+pub mod diff;
+pub mod check;
+pub mod locale;
+pub mod research;
+pub mod quality;
+pub mod recycling;
+pub mod spoilage;
+pub mod surfaces;
+pub mod preferences;
+pub mod solver;
@@ -0,0 +1,199 @@
+//! Localized names from the game's `locale/<lang>/*.cfg` files, so the
+//! planner can take and show names in the player's language. The fetcher
+//! saves them to "locale.data", one `lang<TAB>section<TAB>key<TAB>value` line
+//! per string.
+
+use std::collections::HashMap;
+use std::io;
+use std::io::prelude::*;
+
+/// Sections a prototype name is looked up in, in order of preference.
+const NAME_SECTIONS: [&'static str; 5] = ["item-name", "fluid-name", "entity-name", "recipe-name", "technology-name"];
+
+/// Localized strings of a single language, keyed by section and internal name.
+#[derive(Debug)]
+pub struct Locale {
+    pub lang: String,
+    strings: HashMap<(String, String), String>
+}
+
+fn reference_section(keyword: &str) -> Option<&'static str> {
+    match keyword {
+        "ITEM" => Some("item-name"),
+        "FLUID" => Some("fluid-name"),
+        "ENTITY" => Some("entity-name"),
+        "RECIPE" => Some("recipe-name"),
+        "TECHNOLOGY" => Some("technology-name"),
+        "TILE" => Some("tile-name"),
+        "EQUIPMENT" => Some("equipment-name"),
+        _ => None
+    }
+}
+
+impl Locale {
+    pub fn new(lang: &str) -> Locale {
+        Locale { lang: lang.to_string(), strings: HashMap::new() }
+    }
+
+    /// Adds all strings of a Factorio `locale/<lang>/*.cfg` file.
+    pub fn parse_cfg(&mut self, s: &str) {
+        let mut section = String::new();
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(";") || line.starts_with("#") {
+                continue;
+            }
+            if line.starts_with("[") && line.ends_with("]") {
+                section = line[1..line.len() - 1].to_string();
+                continue;
+            }
+            if let Some(i) = line.find("=") {
+                let key = line[..i].trim().to_string();
+                let value = line[i + 1..].to_string();
+                self.strings.insert((section.clone(), key), value);
+            }
+        }
+    }
+
+    /// Replaces `__ITEM__name__`-like references with the referenced strings.
+    /// Unknown references and parameters like `__1__` are kept as is.
+    pub fn resolve_references(&mut self) {
+        let keys: Vec<_> = self.strings.keys().cloned().collect();
+        for key in keys {
+            let value = self.resolve(&self.strings[&key], 0);
+            self.strings.insert(key, value);
+        }
+    }
+
+    fn resolve(&self, s: &str, depth: usize) -> String {
+        let mut out = String::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("__") {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let keyword_end = after.find("__");
+            let resolved = keyword_end.and_then(|k| {
+                let section = reference_section(&after[..k]);
+                let name_part = &after[k + 2..];
+                match (section, name_part.find("__")) {
+                    (Some(section), Some(n)) => {
+                        let name = &name_part[..n];
+                        let len = 2 + k + 2 + n + 2;
+                        self.get(section, name).map(|x| {
+                            let x = if depth < 8 { self.resolve(x, depth + 1) } else { x.to_string() };
+                            (x, len)
+                        })
+                    },
+                    _ => None
+                }
+            });
+            match resolved {
+                Some((value, len)) => {
+                    out.push_str(&value);
+                    rest = &rest[start + len..];
+                },
+                None => {
+                    out.push_str("__");
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.strings.get(&(section.to_string(), key.to_string())).map(|x| x.as_ref())
+    }
+
+    /// Localized name of an item, fluid, entity, recipe or technology.
+    pub fn name_of(&self, name: &str) -> Option<&str> {
+        NAME_SECTIONS.iter().filter_map(|section| self.get(section, name)).next()
+    }
+
+    /// Localized name if known, internal name otherwise.
+    pub fn display(&self, name: &str) -> String {
+        match self.name_of(name) {
+            Some(x) => x.to_string(),
+            None => name.to_string()
+        }
+    }
+
+    /// Finds an internal name by either internal or localized name (case
+    /// insensitive): the first of `find_all`.
+    pub fn find(&self, query: &str) -> Option<String> {
+        self.find_all(query).into_iter().next()
+    }
+
+    /// All internal names matching `query`: an internal name first, then the
+    /// names with that localized name by section, sorted within a section.
+    pub fn find_all(&self, query: &str) -> Vec<String> {
+        let query = query.to_lowercase();
+        let mut out: Vec<String> = Vec::new();
+        let mut by_value = Vec::new();
+        for section in NAME_SECTIONS.iter() {
+            let mut keys: Vec<_> = self.strings.iter()
+                .filter(|&(&(ref s, _), value)| s == section && value.to_lowercase() == query)
+                .map(|(&(_, ref key), _)| key.clone())
+                .collect();
+            keys.sort();
+            by_value.extend(keys);
+            if let Some(&(_, ref key)) = self.strings.keys().find(|&&(ref s, ref key)| s == section && key.to_lowercase() == query) {
+                if !out.contains(key) {
+                    out.push(key.clone());
+                }
+            }
+        }
+        for key in by_value {
+            if !out.contains(&key) {
+                out.push(key);
+            }
+        }
+        out
+    }
+
+    /// Writes the locale to "locale.data" format: `lang\tsection\tkey\tvalue`.
+    pub fn write<W: Write>(&self, file: &mut W) -> Result<(), io::Error> {
+        let mut keys: Vec<_> = self.strings.keys().collect();
+        keys.sort();
+        for key in keys {
+            writeln!(file, "{}\t{}\t{}\t{}", self.lang, key.0, key.1, self.strings[key])?;
+        }
+        Ok(())
+    }
+
+    /// Reads strings of the language `lang` from "locale.data" contents.
+    pub fn parse_data(s: &str, lang: &str) -> Locale {
+        let mut locale = Locale::new(lang);
+        for line in s.lines() {
+            let parts: Vec<_> = line.splitn(4, "\t").collect();
+            if parts.len() < 4 || parts[0] != lang {
+                continue;
+            }
+            locale.strings.insert((parts[1].to_string(), parts[2].to_string()), parts[3].to_string());
+        }
+        locale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_names_in_a_fixed_order() {
+        let mut locale = Locale::new("en");
+        locale.parse_cfg("[item-name]\nsteel-chest=Chest\niron-chest=Chest\nwooden-chest=Wooden chest\n\
+            [entity-name]\nchest=Box\nwooden-chest=Wooden chest\n");
+        assert_eq!(locale.find_all("chest"), vec!["chest", "iron-chest", "steel-chest"]);
+        assert_eq!(locale.find("CHEST").unwrap(), "chest");
+        assert_eq!(locale.find("wooden chest").unwrap(), "wooden-chest");
+        assert_eq!(locale.find_all("Wooden Chest"), vec!["wooden-chest"]);
+        assert!(locale.find("box-of-nothing").is_none());
+
+        let mut out = Vec::new();
+        locale.write(&mut out).unwrap();
+        let read = Locale::parse_data(&String::from_utf8(out).unwrap(), "en");
+        assert_eq!(read.find_all("chest"), locale.find_all("chest"));
+    }
+}
@@ -0,0 +1,840 @@
+//! Parser of Lua prototype files (`data:extend({...})`).
+//!
+//! A hand-written tokenizer and recursive descent parser. Parsed values
+//! borrow strings from the source buffer where possible (strings without
+//! escape sequences), so parsing doesn't copy the file contents.
+//!
+//! Only tables passed to `data:extend` are parsed, all other code of the file
+//! is tokenized and skipped. Values may be literals, tables and constant
+//! expressions of numbers (`+ - * / % ^`) and strings (`..`). Constants bound
+//! by `local x = <expression>` in the file may be used in later expressions,
+//! e.g. `local hour = 60 * 60 * 60` and `spoil_ticks = 2 * hour`. Fields with
+//! values that can't be evaluated (function calls, unknown variables) are
+//! left out of their table.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::f64;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LuaObject<'a> {
+    pub fields: Vec<(Option<Cow<'a, str>>, LuaField<'a>)>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LuaField<'a> {
+    AString(Cow<'a, str>),
+    AFloat(f64),
+    ABool(bool),
+    AnObject(Box<LuaObject<'a>>)
+}
+
+use self::LuaField::*;
+
+/// Field of a table, the name is `None` for array elements.
+type Field<'a> = (Option<Cow<'a, str>>, LuaField<'a>);
+
+impl<'a> LuaObject<'a> {
+    pub fn new() -> LuaObject<'a> {
+        LuaObject { fields: Vec::new() }
+    }
+
+    /// Adds the named field `key`, builder style.
+    pub fn with(mut self, key: &str, value: LuaField<'a>) -> LuaObject<'a> {
+        self.fields.push((Some(Cow::Owned(key.to_string())), value));
+        self
+    }
+
+    /// Adds an unnamed (array) field.
+    pub fn push(&mut self, value: LuaField<'a>) {
+        self.fields.push((None, value));
+    }
+
+    /// Value of the named field `key`.
+    pub fn get(&self, key: &str) -> Option<&LuaField<'a>> {
+        self.fields.iter()
+            .find(|&&(ref name, _)| name.as_ref().is_some_and(|x| x == key))
+            .map(|&(_, ref field)| field)
+    }
+
+    /// Copies all borrowed strings, detaching the object from the source buffer.
+    pub fn into_owned(self) -> LuaObject<'static> {
+        LuaObject {
+            fields: self.fields.into_iter()
+                .map(|(name, field)| (name.map(|x| Cow::Owned(x.into_owned())), field.into_owned()))
+                .collect()
+        }
+    }
+}
+
+impl<'a> LuaField<'a> {
+    pub fn into_owned(self) -> LuaField<'static> {
+        match self {
+            AString(s) => AString(Cow::Owned(s.into_owned())),
+            AFloat(x) => AFloat(x),
+            ABool(x) => ABool(x),
+            AnObject(obj) => AnObject(Box::new(obj.into_owned()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token<'a> {
+    Ident(&'a str),
+    Str(Cow<'a, str>),
+    Number(f64),
+    Symbol(&'static str),
+    Eof
+}
+
+const SYMBOLS: [&'static str; 26] = [
+    "...", "..", "==", "~=", "<=", ">=", "::", "//",
+    "{", "}", "(", ")", "[", "]", "=", ",", ";", ":", ".", "+", "-", "*", "/", "%", "^", "#"
+];
+
+struct Lexer<'a> {
+    src: &'a str,
+    bytes: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Lexer<'a> {
+    fn new(src: &'a str) -> Lexer<'a> {
+        Lexer { src: src, bytes: src.as_bytes(), pos: 0 }
+    }
+
+    /// Line and column are only computed for errors, to keep the tokenizer fast.
+    fn error_at(&self, pos: usize, message: String) -> ParseError {
+        let line_start = self.src[..pos].rfind('\n').map_or(0, |i| i + 1);
+        let line = self.src[..pos].matches('\n').count() + 1;
+        ParseError { line: line, column: self.src[line_start..pos].chars().count() + 1, message: message }
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        self.error_at(self.pos, message)
+    }
+
+    fn peek_byte(&self, offset: usize) -> u8 {
+        *self.bytes.get(self.pos + offset).unwrap_or(&0)
+    }
+
+    /// Level of a long bracket `[==[` starting at the current position.
+    fn long_bracket_level(&self) -> Option<usize> {
+        if self.peek_byte(0) != b'[' {
+            return None;
+        }
+        let mut level = 0;
+        while self.peek_byte(1 + level) == b'=' {
+            level += 1;
+        }
+        if self.peek_byte(1 + level) == b'[' { Some(level) } else { None }
+    }
+
+    /// Reads `[==[ ... ]==]` and returns its contents.
+    fn long_bracket(&mut self, level: usize) -> Result<&'a str, ParseError> {
+        self.pos += level + 2;
+        if self.peek_byte(0) == b'\r' {
+            self.pos += 1;
+        }
+        if self.peek_byte(0) == b'\n' {
+            self.pos += 1;
+        }
+        let close = format!("]{}]", "=".repeat(level));
+        match self.src[self.pos..].find(&close) {
+            Some(i) => {
+                let s = &self.src[self.pos..self.pos + i];
+                self.pos += i + close.len();
+                Ok(s)
+            },
+            None => Err(self.error("unfinished long string or comment".to_string()))
+        }
+    }
+
+    fn skip_spaces_and_comments(&mut self) -> Result<(), ParseError> {
+        loop {
+            match self.peek_byte(0) {
+                b' ' | b'\t' | b'\r' | b'\n' => self.pos += 1,
+                b'-' if self.peek_byte(1) == b'-' => {
+                    self.pos += 2;
+                    if let Some(level) = self.long_bracket_level() {
+                        self.long_bracket(level)?;
+                    } else {
+                        let end = self.src[self.pos..].find('\n').map_or(self.src.len(), |i| self.pos + i);
+                        self.pos = end;
+                    }
+                },
+                _ => return Ok(())
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Token<'a>, ParseError> {
+        self.skip_spaces_and_comments()?;
+        let c = self.peek_byte(0);
+        if self.pos >= self.bytes.len() {
+            return Ok(Token::Eof);
+        }
+        if c == b'"' || c == b'\'' {
+            return self.string(c);
+        }
+        if let Some(level) = self.long_bracket_level() {
+            return self.long_bracket(level).map(|s| Token::Str(Cow::Borrowed(s)));
+        }
+        if c.is_ascii_digit() || (c == b'.' && self.peek_byte(1).is_ascii_digit()) {
+            return self.number();
+        }
+        if c.is_ascii_alphabetic() || c == b'_' {
+            let start = self.pos;
+            while self.peek_byte(0).is_ascii_alphanumeric() || self.peek_byte(0) == b'_' {
+                self.pos += 1;
+            }
+            return Ok(Token::Ident(&self.src[start..self.pos]));
+        }
+        for symbol in SYMBOLS.iter() {
+            if self.src[self.pos..].starts_with(symbol) {
+                self.pos += symbol.len();
+                return Ok(Token::Symbol(symbol));
+            }
+        }
+        let symbol = self.src[self.pos..].chars().next().unwrap();
+        self.pos += symbol.len_utf8();
+        Ok(Token::Symbol("?"))
+    }
+
+    fn number(&mut self) -> Result<Token<'a>, ParseError> {
+        let start = self.pos;
+        if self.peek_byte(0) == b'0' && (self.peek_byte(1) == b'x' || self.peek_byte(1) == b'X') {
+            self.pos += 2;
+            while self.peek_byte(0).is_ascii_hexdigit() {
+                self.pos += 1;
+            }
+            return match u64::from_str_radix(&self.src[start + 2..self.pos], 16) {
+                Ok(x) => Ok(Token::Number(x as f64)),
+                Err(why) => Err(self.error_at(start, format!("illegal number: {}", why)))
+            };
+        }
+        while self.peek_byte(0).is_ascii_digit() || self.peek_byte(0) == b'.' {
+            if self.peek_byte(0) == b'.' && self.peek_byte(1) == b'.' {
+                break;
+            }
+            self.pos += 1;
+        }
+        if self.peek_byte(0) == b'e' || self.peek_byte(0) == b'E' {
+            self.pos += 1;
+            if self.peek_byte(0) == b'+' || self.peek_byte(0) == b'-' {
+                self.pos += 1;
+            }
+            while self.peek_byte(0).is_ascii_digit() {
+                self.pos += 1;
+            }
+        }
+        match f64::from_str(&self.src[start..self.pos]) {
+            Ok(x) => Ok(Token::Number(x)),
+            Err(why) => Err(self.error_at(start, format!("illegal number {}: {}", &self.src[start..self.pos], why)))
+        }
+    }
+
+    /// Reads a quoted string. Strings without escape sequences are borrowed.
+    /// Escaped bytes (`\xC3\xA9`) are collected as bytes, so the string must
+    /// be valid UTF-8 once they are all in.
+    fn string(&mut self, quote: u8) -> Result<Token<'a>, ParseError> {
+        let start = self.pos + 1;
+        let mut i = start;
+        while i < self.bytes.len() && self.bytes[i] != quote && self.bytes[i] != b'\\' && self.bytes[i] != b'\n' {
+            i += 1;
+        }
+        if i < self.bytes.len() && self.bytes[i] == quote {
+            self.pos = i + 1;
+            return Ok(Token::Str(Cow::Borrowed(&self.src[start..i])));
+        }
+        let mut s = self.bytes[start..i].to_vec();
+        self.pos = i;
+        loop {
+            match self.bytes.get(self.pos) {
+                None | Some(&b'\n') => return Err(self.error_at(start - 1, "unfinished string".to_string())),
+                Some(&c) if c == quote => {
+                    self.pos += 1;
+                    return match String::from_utf8(s) {
+                        Ok(s) => Ok(Token::Str(Cow::Owned(s))),
+                        Err(_) => Err(self.error_at(start - 1, "string is not valid UTF-8".to_string()))
+                    };
+                },
+                Some(&b'\\') => {
+                    self.pos += 1;
+                    self.escape(&mut s)?;
+                },
+                Some(&c) => {
+                    s.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Reads the escape sequence after a `\`, errors point at the `\`.
+    fn escape(&mut self, s: &mut Vec<u8>) -> Result<(), ParseError> {
+        let backslash = self.pos - 1;
+        let c = self.peek_byte(0);
+        self.pos += 1;
+        match c {
+            b'n' => s.push(b'\n'),
+            b't' => s.push(b'\t'),
+            b'r' => s.push(b'\r'),
+            b'a' => s.push(0x07),
+            b'b' => s.push(0x08),
+            b'f' => s.push(0x0c),
+            b'v' => s.push(0x0b),
+            b'\\' | b'"' | b'\'' => s.push(c),
+            // A line break, `\r\n` and `\n\r` count as one.
+            b'\n' | b'\r' => {
+                let other = if c == b'\n' { b'\r' } else { b'\n' };
+                if self.peek_byte(0) == other {
+                    self.pos += 1;
+                }
+                s.push(b'\n');
+            },
+            b'z' => {
+                self.skip_whitespace();
+            },
+            b'x' => {
+                let hex = self.src.get(self.pos..self.pos + 2).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(x) => s.push(x),
+                    Err(_) => return Err(self.error_at(backslash, "illegal \\x escape".to_string()))
+                }
+                self.pos += 2;
+            },
+            b'u' => {
+                let end = match self.src[self.pos..].find('}') {
+                    Some(i) if self.peek_byte(0) == b'{' => self.pos + i,
+                    _ => return Err(self.error_at(backslash, "illegal \\u escape".to_string()))
+                };
+                let c = u32::from_str_radix(&self.src[self.pos + 1..end], 16).ok().and_then(::std::char::from_u32);
+                match c {
+                    Some(c) => s.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                    None => return Err(self.error_at(backslash, "illegal \\u escape".to_string()))
+                }
+                self.pos = end + 1;
+            },
+            b'0'..=b'9' => {
+                let start = self.pos - 1;
+                while self.pos < start + 3 && self.peek_byte(0).is_ascii_digit() {
+                    self.pos += 1;
+                }
+                match u8::from_str(&self.src[start..self.pos]) {
+                    Ok(x) => s.push(x),
+                    Err(_) => return Err(self.error_at(backslash, "illegal decimal escape".to_string()))
+                }
+            },
+            _ => {
+                let c = self.src[backslash + 1..].chars().next().unwrap_or(' ');
+                return Err(self.error_at(backslash, format!("illegal escape sequence \\{}", c)));
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek_byte(0).is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    /// Current token and its start position.
+    token: Token<'a>,
+    token_pos: usize,
+    /// Lookahead token, if it was requested.
+    next: Option<(Token<'a>, usize)>,
+    /// Values of the constants bound by `local`.
+    locals: HashMap<&'a str, LuaField<'a>>,
+    /// Set with the error of an expression that can't be evaluated, which
+    /// skips the value instead of failing the file.
+    unsupported: bool
+}
+
+/// Position of the parser, to go back to.
+struct Mark<'a> {
+    pos: usize,
+    token: Token<'a>,
+    token_pos: usize,
+    next: Option<(Token<'a>, usize)>
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str) -> Result<Parser<'a>, ParseError> {
+        let mut lexer = Lexer::new(src);
+        lexer.skip_spaces_and_comments()?;
+        let pos = lexer.pos;
+        let token = lexer.next_token()?;
+        Ok(Parser { lexer: lexer, token: token, token_pos: pos, next: None, locals: HashMap::new(), unsupported: false })
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        self.lexer.error_at(self.token_pos, message)
+    }
+
+    /// Error of an expression that is valid Lua, but not a constant.
+    fn unsupported(&mut self, message: String) -> ParseError {
+        self.unsupported = true;
+        self.error(message)
+    }
+
+    fn mark(&self) -> Mark<'a> {
+        Mark { pos: self.lexer.pos, token: self.token.clone(), token_pos: self.token_pos, next: self.next.clone() }
+    }
+
+    fn reset(&mut self, mark: Mark<'a>) {
+        self.lexer.pos = mark.pos;
+        self.token = mark.token;
+        self.token_pos = mark.token_pos;
+        self.next = mark.next;
+        self.unsupported = false;
+    }
+
+    fn bump(&mut self) -> Result<Token<'a>, ParseError> {
+        let (token, pos) = match self.next.take() {
+            Some(next) => next,
+            None => {
+                self.lexer.skip_spaces_and_comments()?;
+                let pos = self.lexer.pos;
+                (self.lexer.next_token()?, pos)
+            }
+        };
+        self.token_pos = pos;
+        Ok(::std::mem::replace(&mut self.token, token))
+    }
+
+    fn lookahead(&mut self) -> Result<&Token<'a>, ParseError> {
+        if self.next.is_none() {
+            self.lexer.skip_spaces_and_comments()?;
+            let pos = self.lexer.pos;
+            self.next = Some((self.lexer.next_token()?, pos));
+        }
+        Ok(&self.next.as_ref().unwrap().0)
+    }
+
+    fn is_symbol(&self, symbol: &'static str) -> bool {
+        self.token == Token::Symbol(symbol)
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), ParseError> {
+        if !self.is_symbol(symbol) {
+            return Err(self.error(format!("expected \"{}\", found {:?}", symbol, self.token)));
+        }
+        self.bump()?;
+        Ok(())
+    }
+
+    /// Checks for `data:extend(` or `data:extend` followed by a table at the
+    /// current token and skips it. Returns whether the argument is in
+    /// parentheses, `None` if there is no `data:extend`.
+    fn data_extend(&mut self) -> Result<Option<bool>, ParseError> {
+        if self.token != Token::Ident("data") || *self.lookahead()? != Token::Symbol(":") {
+            return Ok(None);
+        }
+        self.bump()?;
+        self.bump()?;
+        if self.token != Token::Ident("extend") {
+            return Ok(None);
+        }
+        self.bump()?;
+        if self.is_symbol("{") {
+            return Ok(Some(false));
+        }
+        self.expect("(")?;
+        Ok(Some(true))
+    }
+
+    /// Reads `local <name> = <expression>` at the current token. The name is
+    /// unbound again if the value isn't a constant.
+    fn local(&mut self) -> Result<(), ParseError> {
+        self.bump()?;
+        let name = match self.token.clone() {
+            Token::Ident(name) if *self.lookahead()? == Token::Symbol("=") => name,
+            _ => return Ok(())
+        };
+        self.bump()?;
+        self.bump()?;
+        match self.expression() {
+            Ok(Some(value)) if !self.is_symbol(",") => { self.locals.insert(name, value); },
+            Ok(_) => { self.locals.remove(name); },
+            Err(_) if self.unsupported => {
+                self.unsupported = false;
+                self.locals.remove(name);
+            },
+            Err(why) => return Err(why)
+        }
+        Ok(())
+    }
+
+    fn file(&mut self) -> Result<Vec<LuaObject<'a>>, ParseError> {
+        let mut objects = Vec::new();
+        while self.token != Token::Eof {
+            if self.token == Token::Ident("local") {
+                self.local()?;
+                continue;
+            }
+            let parentheses = match self.data_extend()? {
+                Some(parentheses) => parentheses,
+                None => {
+                    self.bump()?;
+                    continue;
+                }
+            };
+            let pos = self.token_pos;
+            for (_, field) in self.table()?.fields {
+                match field {
+                    AnObject(obj) => objects.push(*obj),
+                    _ => return Err(self.lexer.error_at(pos, "top level primitive found".to_string()))
+                }
+            }
+            if parentheses {
+                self.expect(")")?;
+            }
+        }
+        Ok(objects)
+    }
+
+    fn table(&mut self) -> Result<LuaObject<'a>, ParseError> {
+        self.expect("{")?;
+        let mut obj = LuaObject::new();
+        while !self.is_symbol("}") {
+            let mark = self.mark();
+            match self.field() {
+                Ok(Some(field)) => obj.fields.push(field),
+                Ok(None) => (),
+                Err(_) if self.unsupported => {
+                    self.reset(mark);
+                    self.skip_field()?;
+                },
+                Err(why) => return Err(why)
+            }
+            if self.is_symbol(",") || self.is_symbol(";") {
+                self.bump()?;
+            } else if !self.is_symbol("}") {
+                return Err(self.error(format!("expected \",\" or \"}}\", found {:?}", self.token)));
+            }
+        }
+        self.bump()?;
+        Ok(obj)
+    }
+
+    /// Reads a field of a table, `None` if its value is `nil`.
+    fn field(&mut self) -> Result<Option<Field<'a>>, ParseError> {
+        let key = if self.is_symbol("[") {
+            self.bump()?;
+            let key = match self.expression()? {
+                Some(AString(s)) => s,
+                Some(AFloat(x)) => Cow::Owned(x.to_string()),
+                _ => return Err(self.error("table key must be a string or a number".to_string()))
+            };
+            self.expect("]")?;
+            self.expect("=")?;
+            Some(key)
+        } else if let Token::Ident(name) = self.token.clone() {
+            if *self.lookahead()? == Token::Symbol("=") {
+                self.bump()?;
+                self.bump()?;
+                Some(Cow::Borrowed(name))
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+        let value = self.expression()?;
+        if self.is_operator() {
+            return Err(self.unsupported(format!("unsupported operator: {:?}", self.token)));
+        }
+        Ok(value.map(|value| (key, value)))
+    }
+
+    /// Comparisons and logical operators, which constant expressions lack.
+    fn is_operator(&self) -> bool {
+        match self.token {
+            Token::Symbol(s) => ["==", "~=", "<=", ">=", "//", "?"].contains(&s),
+            Token::Ident(s) => s == "and" || s == "or",
+            _ => false
+        }
+    }
+
+    /// Skips tokens up to the end of the current field.
+    fn skip_field(&mut self) -> Result<(), ParseError> {
+        let mut depth = 0;
+        loop {
+            match self.token {
+                Token::Symbol(",") | Token::Symbol(";") | Token::Symbol("}") if depth == 0 => return Ok(()),
+                Token::Symbol("{") | Token::Symbol("(") | Token::Symbol("[") => depth += 1,
+                Token::Ident("function") | Token::Ident("if") | Token::Ident("do") => depth += 1,
+                Token::Symbol("}") | Token::Symbol(")") | Token::Symbol("]") | Token::Ident("end") => depth -= 1,
+                Token::Eof => return Err(self.error("unfinished table".to_string())),
+                _ => ()
+            }
+            self.bump()?;
+        }
+    }
+
+    fn number(&mut self, value: Option<LuaField<'a>>) -> Result<f64, ParseError> {
+        match value {
+            Some(AFloat(x)) => Ok(x),
+            _ => Err(self.unsupported("arithmetic on a non-number value".to_string()))
+        }
+    }
+
+    /// Constant expression, `None` stands for `nil`.
+    fn expression(&mut self) -> Result<Option<LuaField<'a>>, ParseError> {
+        let left = self.additive()?;
+        if !self.is_symbol("..") {
+            return Ok(left);
+        }
+        self.bump()?;
+        let right = self.expression()?;
+        let to_string = |x: Option<LuaField<'a>>| match x {
+            Some(AString(s)) => Some(s.into_owned()),
+            Some(AFloat(x)) => Some(x.to_string()),
+            _ => None
+        };
+        match (to_string(left), to_string(right)) {
+            (Some(a), Some(b)) => Ok(Some(AString(Cow::Owned(a + &b)))),
+            _ => Err(self.unsupported("concatenation of a non-string value".to_string()))
+        }
+    }
+
+    fn additive(&mut self) -> Result<Option<LuaField<'a>>, ParseError> {
+        let mut left = self.multiplicative()?;
+        while self.is_symbol("+") || self.is_symbol("-") {
+            let plus = self.is_symbol("+");
+            self.bump()?;
+            let a = self.number(left)?;
+            let right = self.multiplicative()?;
+            let b = self.number(right)?;
+            left = Some(AFloat(if plus { a + b } else { a - b }));
+        }
+        Ok(left)
+    }
+
+    fn multiplicative(&mut self) -> Result<Option<LuaField<'a>>, ParseError> {
+        let mut left = self.unary()?;
+        while self.is_symbol("*") || self.is_symbol("/") || self.is_symbol("%") {
+            let op = self.bump()?;
+            let a = self.number(left)?;
+            let right = self.unary()?;
+            let b = self.number(right)?;
+            left = Some(AFloat(match op {
+                Token::Symbol("*") => a * b,
+                Token::Symbol("/") => a / b,
+                _ => a - (a / b).floor() * b
+            }));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Option<LuaField<'a>>, ParseError> {
+        if self.is_symbol("-") {
+            self.bump()?;
+            let value = self.unary()?;
+            let x = self.number(value)?;
+            return Ok(Some(AFloat(-x)));
+        }
+        let base = self.primary()?;
+        if !self.is_symbol("^") {
+            return Ok(base);
+        }
+        self.bump()?;
+        let a = self.number(base)?;
+        let exponent = self.unary()?;
+        let b = self.number(exponent)?;
+        Ok(Some(AFloat(a.powf(b))))
+    }
+
+    fn primary(&mut self) -> Result<Option<LuaField<'a>>, ParseError> {
+        match self.token.clone() {
+            Token::Number(x) => {
+                self.bump()?;
+                Ok(Some(AFloat(x)))
+            },
+            Token::Str(_) => match self.bump()? {
+                Token::Str(s) => Ok(Some(AString(s))),
+                _ => unreachable!()
+            },
+            Token::Ident("true") => {
+                self.bump()?;
+                Ok(Some(ABool(true)))
+            },
+            Token::Ident("false") => {
+                self.bump()?;
+                Ok(Some(ABool(false)))
+            },
+            Token::Ident("nil") => {
+                self.bump()?;
+                Ok(None)
+            },
+            Token::Ident("math") if *self.lookahead()? == Token::Symbol(".") => {
+                self.bump()?;
+                self.bump()?;
+                if self.token != Token::Ident("huge") {
+                    return Err(self.unsupported(format!("unsupported expression: math.{:?}", self.token)));
+                }
+                self.bump()?;
+                Ok(Some(AFloat(f64::INFINITY)))
+            },
+            Token::Ident(name) if self.locals.contains_key(name) && !self.indexed()? => {
+                self.bump()?;
+                Ok(self.locals.get(name).cloned())
+            },
+            Token::Symbol("{") => Ok(Some(AnObject(Box::new(self.table()?)))),
+            Token::Symbol("(") => {
+                self.bump()?;
+                let value = self.expression()?;
+                self.expect(")")?;
+                Ok(value)
+            },
+            token => Err(self.unsupported(format!("unsupported expression: {:?}", token)))
+        }
+    }
+
+    /// Checks if the name at the current token is indexed or called.
+    fn indexed(&mut self) -> Result<bool, ParseError> {
+        Ok(match *self.lookahead()? {
+            Token::Symbol(s) => [".", "[", "(", ":", "{"].contains(&s),
+            Token::Str(_) => true,
+            _ => false
+        })
+    }
+}
+
+/// Parses a prototype file and returns all prototypes from its `data:extend` calls.
+pub fn parse_file<'a>(s: &'a str) -> Result<Vec<LuaObject<'a>>, ParseError> {
+    Parser::new(s)?.file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(src: &str) -> Vec<Token<'_>> {
+        let mut lexer = Lexer::new(src);
+        let mut out = Vec::new();
+        loop {
+            match lexer.next_token().unwrap() {
+                Token::Eof => return out,
+                token => out.push(token)
+            }
+        }
+    }
+
+    fn first(src: &str) -> LuaObject<'_> {
+        parse_file(src).unwrap().remove(0)
+    }
+
+    #[test]
+    fn tokenizes_numbers_symbols_and_comments() {
+        assert_eq!(tokens("x = 0x1F + 2.5e2 .. .5 -- comment\n--[==[ long\n]] comment ]==] ~= ..."), vec![
+            Token::Ident("x"), Token::Symbol("="), Token::Number(31.0), Token::Symbol("+"), Token::Number(250.0),
+            Token::Symbol(".."), Token::Number(0.5), Token::Symbol("~="), Token::Symbol("...")
+        ]);
+    }
+
+    #[test]
+    fn borrows_strings_without_escapes() {
+        match tokens(r#""plain" 'esc\"aped\65\x41\u{263A}' [[long]]"#).as_slice() {
+            [Token::Str(Cow::Borrowed("plain")), Token::Str(Cow::Owned(s)), Token::Str(Cow::Borrowed("long"))] =>
+                assert_eq!(s, "esc\"apedAA\u{263A}"),
+            x => panic!("unexpected tokens {:?}", x)
+        }
+    }
+
+    #[test]
+    fn decodes_escaped_bytes_as_utf8() {
+        assert_eq!(tokens("'\\xC3\\xA9' '\\195\\169' 'a\\\r\nb'"), vec![
+            Token::Str(Cow::Owned("\u{e9}".to_string())), Token::Str(Cow::Owned("\u{e9}".to_string())),
+            Token::Str(Cow::Owned("a\nb".to_string()))
+        ]);
+        let error = parse_file("data:extend({{name = 'a\\xC3'}})").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 22));
+        let error = parse_file("data:extend({{name = '\u{e9}\\\u{e9}'}})").unwrap_err();
+        assert_eq!((error.line, error.column, &error.message[..]), (1, 24, "illegal escape sequence \\\u{e9}"));
+    }
+
+    #[test]
+    fn evaluates_constant_expressions() {
+        let obj = first("data:extend({{a = 1 + 2 * 3, b = -2 ^ 2, c = (1 + 2) * 3, d = 7 % 4, e = 'x' .. 1 .. 'y', f = math.huge, g = nil}})");
+        assert_eq!(obj.get("a"), Some(&AFloat(7.0)));
+        assert_eq!(obj.get("b"), Some(&AFloat(-4.0)));
+        assert_eq!(obj.get("c"), Some(&AFloat(9.0)));
+        assert_eq!(obj.get("d"), Some(&AFloat(3.0)));
+        assert_eq!(obj.get("e"), Some(&AString(Cow::Borrowed("x1y"))));
+        assert_eq!(obj.get("f"), Some(&AFloat(f64::INFINITY)));
+        assert_eq!(obj.get("g"), None);
+    }
+
+    #[test]
+    fn reads_keys_arrays_and_skips_other_code() {
+        let src = "local util = require('util')\nfunction f() return {1} end\n\
+                   data:extend({{name = 'a', [\"b-c\"] = true, [2] = false; {1, 2}}})\n\
+                   data:extend({{name = 'b'}})";
+        let objects = parse_file(src).unwrap();
+        assert_eq!(objects.len(), 2);
+        let a = &objects[0];
+        assert_eq!(a.get("name"), Some(&AString(Cow::Borrowed("a"))));
+        assert_eq!(a.get("b-c"), Some(&ABool(true)));
+        assert_eq!(a.get("2"), Some(&ABool(false)));
+        assert_eq!(a.fields[3], (None, AnObject(Box::new(LuaObject {
+            fields: vec![(None, AFloat(1.0)), (None, AFloat(2.0))]
+        }))));
+        assert_eq!(objects[1].get("name"), Some(&AString(Cow::Borrowed("b"))));
+        assert_eq!(first("data:extend{{name = 'c'}}").get("name"), Some(&AString(Cow::Borrowed("c"))));
+    }
+
+    #[test]
+    fn uses_local_constants() {
+        let src = "local second = 60\nlocal hour = 60 * 60 * second\nlocal kg = 1000\nlocal name = 'ice'\n\
+                   local sounds = require('sounds')\n\
+                   data:extend({{name = name .. '-cube', spoil_ticks = 2 * hour, weight = 5 * kg, sound = sounds.ice}})";
+        let obj = first(src);
+        assert_eq!(obj.get("name"), Some(&AString(Cow::Borrowed("ice-cube"))));
+        assert_eq!(obj.get("weight"), Some(&AFloat(5000.0)));
+        assert_eq!(obj.get("sound"), None);
+        assert_eq!(obj.get("spoil_ticks"), Some(&AFloat(2.0 * 216000.0)));
+    }
+
+    #[test]
+    fn leaves_out_fields_that_arent_constant() {
+        let src = "data:extend({{\n  name = 'a',\n  icons = util.icons({1, 2}),\n  spoil_ticks = 1 * hour,\n\
+                   pictures = {layers = {{filename = path .. '/a.png', size = 64}}},\n\
+                   on_spoil = function(x) if x then return {1, 2} end end,\n  flag = a == b,\n  [key] = 1,\n  count = 3\n}})";
+        let obj = first(src);
+        let names: Vec<_> = obj.fields.iter().map(|x| x.0.as_ref().unwrap().to_string()).collect();
+        assert_eq!(names, vec!["name", "pictures", "count"]);
+        assert_eq!(obj.get("pictures"), Some(&AnObject(Box::new(LuaObject::new().with("layers", AnObject(Box::new(LuaObject {
+            fields: vec![(None, AnObject(Box::new(LuaObject::new().with("size", AFloat(64.0)))))]
+        })))))));
+    }
+
+    #[test]
+    fn reports_errors_with_line_and_column() {
+        let error = parse_file("data:extend({\n  {name = 'a' 'b'}\n})").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 15));
+        let error = parse_file("data:extend({{name = \"a\n\"}})").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 22));
+        assert!(parse_file("data:extend({1})").is_err());
+    }
+}
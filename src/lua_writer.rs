@@ -0,0 +1,160 @@
+//! Pretty-printer of Lua prototypes, the inverse of `lua::parse_file`.
+
+use std::f64;
+use lua::{LuaObject, LuaField};
+use lua::LuaField::*;
+
+const INDENT: &'static str = "  ";
+
+/// Writes prototypes as a Lua `data:extend({...})` call.
+pub fn to_lua_source(objects: &[LuaObject]) -> String {
+    let mut out = String::from("data:extend(\n{\n");
+    for obj in objects {
+        out.push_str(INDENT);
+        write_object(&mut out, obj, 1);
+        out.push_str(",\n");
+    }
+    out.push_str("})\n");
+    out
+}
+
+/// Writes a single Lua value, nested tables are indented by `indent` levels.
+pub fn write_value(out: &mut String, field: &LuaField, indent: usize) {
+    match *field {
+        AString(ref s) => write_string(out, s),
+        AFloat(x) => write_number(out, x),
+        ABool(x) => out.push_str(if x { "true" } else { "false" }),
+        AnObject(ref obj) => write_object(out, obj, indent)
+    }
+}
+
+/// Arrays of primitives like `{"iron-plate", 2}` are written on one line,
+/// other tables with one field per line.
+pub fn write_object(out: &mut String, obj: &LuaObject, indent: usize) {
+    if obj.fields.is_empty() {
+        out.push_str("{}");
+        return;
+    }
+    let inline = obj.fields.iter().all(|&(ref name, ref field)| name.is_none() && !is_object(field));
+    if inline {
+        out.push('{');
+        for (i, &(_, ref field)) in obj.fields.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            write_value(out, field, indent);
+        }
+        out.push('}');
+        return;
+    }
+    out.push_str("{\n");
+    for (i, &(ref name, ref field)) in obj.fields.iter().enumerate() {
+        for _ in 0..indent + 1 {
+            out.push_str(INDENT);
+        }
+        if let Some(ref name) = *name {
+            write_key(out, name);
+            out.push_str(" = ");
+        }
+        write_value(out, field, indent + 1);
+        if i + 1 < obj.fields.len() {
+            out.push(',');
+        }
+        out.push('\n');
+    }
+    for _ in 0..indent {
+        out.push_str(INDENT);
+    }
+    out.push('}');
+}
+
+fn is_object(field: &LuaField) -> bool {
+    matches!(*field, AnObject(_))
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+        _ => return false
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn write_key(out: &mut String, key: &str) {
+    if is_identifier(key) {
+        out.push_str(key);
+    } else {
+        out.push('[');
+        write_string(out, key);
+        out.push(']');
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+}
+
+fn write_number(out: &mut String, x: f64) {
+    if x.is_nan() {
+        out.push_str("(0/0)");
+    } else if x == f64::INFINITY {
+        out.push_str("math.huge");
+    } else if x == f64::NEG_INFINITY {
+        out.push_str("-math.huge");
+    } else {
+        out.push_str(&x.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lua::parse_file;
+    use super::*;
+
+    #[test]
+    fn written_source_parses_to_the_same_prototypes() {
+        let src = r#"
+            data:extend({
+              {
+                type = "recipe",
+                name = "rocket-part",
+                enabled = false,
+                energy_required = 3,
+                ingredients = {{"rocket-control-unit", 10}, {type = "fluid", name = "water", amount = 0.5}},
+                results = {},
+                ["dash-key"] = 'say "hi"\n\tand\\bye',
+                max = math.huge,
+                min = -2.5e-3
+              },
+              {type = "item", name = "wood", stack_size = 100, flags = {"goes-to-main-inventory"}}
+            })
+        "#;
+        let objects = parse_file(src).unwrap();
+        assert_eq!(objects.len(), 2);
+        let written = to_lua_source(&objects);
+        assert_eq!(parse_file(&written).unwrap(), objects);
+        assert_eq!(to_lua_source(&parse_file(&written).unwrap()), written);
+    }
+
+    #[test]
+    fn writes_short_arrays_on_one_line() {
+        let obj = LuaObject::new().with("ingredients", AnObject(Box::new(LuaObject {
+            fields: vec![(None, AString("iron-plate".into())), (None, AFloat(2.0))]
+        })));
+        let mut out = String::new();
+        write_object(&mut out, &obj, 0);
+        assert_eq!(out, "{\n  ingredients = {\"iron-plate\", 2}\n}");
+    }
+}
@@ -1,353 +1,401 @@
-#![feature(box_syntax)]
-#![feature(box_patterns)]
-#![feature(i128_type)]
+#![allow(unknown_lints)]
+#![allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes, clippy::needless_borrowed_reference)]
 
-#[macro_use]
-extern crate nom;
+extern crate factorio_recipes_planner;
 
-mod structs;
-
-use structs::*;
+use factorio_recipes_planner::{cache, check, data_file, lua, lua_writer, mod_info, profiles, query};
+use factorio_recipes_planner::data_file::{DataSet, Stamp};
+use factorio_recipes_planner::cache::Cache;
+use factorio_recipes_planner::structs::*;
+use factorio_recipes_planner::prototypes::*;
+use factorio_recipes_planner::locale::Locale;
+use factorio_recipes_planner::preferences::MachinePreferences;
+use std::env;
 use std::io;
 use std::fs;
-use std::str;
 use std::fs::File;
 use std::io::prelude::*;
-use std::path::Path;
-use std::collections::{HashMap, HashSet};
-use std::mem;
-use nom::IResult::Done;
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
 
 const FACTORIO_BASE: &'static str = "C:\\Program Files\\Factorio\\";
-const BASE_RECIPE_PATH: &'static str = "data\\base\\prototypes\\recipe\\";
+const DATA_PATH: &'static str = "data\\";
+const BASE_LOCALE_PATH: &'static str = "data\\base\\locale\\";
+const BASE_PROTOTYPES_PATH: &'static str = "data\\base\\prototypes\\";
+const CACHE_PATH: &'static str = "fetch.cache";
 
-#[derive(Debug, Clone)]
-struct LuaObject {
-    fields: Vec<(Option<String>, LuaField)>
+/// Recipes of a prototype file in the data file format.
+fn convert_recipes(path: &Path, s: &str, format: Format) -> String {
+    let mut recipes = Vec::new();
+    match lua::parse_file(s) {
+        Ok(vec) => for elem in vec {
+            match to_recipe(elem, format) {
+                Ok(Some(recipe)) => recipes.push(recipe),
+                Ok(None) => (),
+                Err(why) => println!("Skipped recipe: {}", why)
+            }
+        },
+        Err(why) => println!("Skipped file {}:{}", path.display(), why)
+    }
+    let mut out = Vec::new();
+    data_file::write_recipes(&mut out, &recipes).unwrap();
+    String::from_utf8(out).unwrap()
 }
 
-#[derive(Debug, Clone)]
-enum LuaField {
-    AString(String),
-    AFloat(f64),
-    ABool(bool),
-    AnObject(Box<LuaObject>)
-}
+/// Prototype types of machines, which stats are stored in the data file.
+const MACHINE_TYPES: [&'static str; 5] = ["assembling-machine", "furnace", "rocket-silo", "mining-drill", "lab"];
 
-use LuaField::*;
+/// Machines of a prototype file in the data file format.
+fn convert_machines(path: &Path, s: &str) -> String {
+    let mut machines = Vec::new();
+    match lua::parse_file(s) {
+        Ok(vec) => for elem in vec {
+            let is_machine = match elem.get("type") {
+                Some(&lua::LuaField::AString(ref t)) => MACHINE_TYPES.contains(&&t[..]),
+                _ => false
+            };
+            if !is_machine {
+                continue;
+            }
+            match from_prototype::<Entity>(elem) {
+                Ok(machine) => machines.push(machine),
+                Err(why) => println!("Skipped machine: {}", why)
+            }
+        },
+        Err(why) => println!("Skipped file {}:{}", path.display(), why)
+    }
+    let mut out = Vec::new();
+    let data = DataSet { machines: machines, ..DataSet::default() };
+    data_file::write_data(&mut out, &data).unwrap();
+    String::from_utf8(out).unwrap()
+}
 
-named!(parse_bool<bool>, alt!(
-    tag!("false") => {|_| false} |
-    tag!("true")  => {|_| true} 
-));
+/// Items of a prototype file that spoil or have a weight, in the data file format.
+fn convert_items(path: &Path, s: &str) -> String {
+    let mut items = Vec::new();
+    match lua::parse_file(s) {
+        Ok(vec) => for elem in vec {
+            if elem.get("spoil_ticks").is_none() && elem.get("weight").is_none() {
+                continue;
+            }
+            match from_prototype::<Item>(elem) {
+                Ok(item) => items.push(item),
+                Err(why) => println!("Skipped item: {}", why)
+            }
+        },
+        Err(why) => println!("Skipped file {}:{}", path.display(), why)
+    }
+    let mut out = Vec::new();
+    let data = DataSet { items: items, ..DataSet::default() };
+    data_file::write_data(&mut out, &data).unwrap();
+    String::from_utf8(out).unwrap()
+}
 
-named!(parse_string<&str>, alt!(
-    chain!(
-        tag!("\"")        ~
-        s: is_not!("\"")  ~
-        tag!("\"")        ,
-        || {str::from_utf8(s).unwrap()}
-    ) => {|x| x} |
-    tag!("\"\"") => {|_| ""}
-));
+/// Technologies of a prototype file in the data file format. Technologies
+/// without fixed unit count are kept as `# infinite` comments.
+fn convert_technologies(path: &Path, s: &str) -> String {
+    let mut technologies = Vec::new();
+    let mut infinite = Vec::new();
+    match lua::parse_file(s) {
+        Ok(vec) => for elem in vec {
+            let name = match elem.get("name") {
+                Some(&lua::LuaField::AString(ref s)) => s.to_string(),
+                _ => "?".to_string()
+            };
+            match to_technology(elem) {
+                Ok(Some(technology)) => technologies.push(technology),
+                Ok(None) => infinite.push(name),
+                Err(why) => println!("Skipped technology: {}", why)
+            }
+        },
+        Err(why) => println!("Skipped file {}:{}", path.display(), why)
+    }
+    let mut out = Vec::new();
+    let data = DataSet { technologies: technologies, ..DataSet::default() };
+    data_file::write_data(&mut out, &data).unwrap();
+    for name in infinite {
+        writeln!(out, "# infinite\t{}", name).unwrap();
+    }
+    String::from_utf8(out).unwrap()
+}
 
-named!(parse_float<f64>, chain!(
-    minus: tag!("-")?            ~
-    integer: is_a!("0123456789") ~
-    fraction: chain!(
-        tag!(".")              ~
-        s: is_a!("0123456789") ,
-        || {s}
-    )?                           ,
-    || {
-        let sign = if minus.is_some() {-1.0} else {1.0};
-        let mut s = str::from_utf8(integer).unwrap().to_string();
-        if let Some(frac) = fraction {
-            s = format!("{}.{}", s, str::from_utf8(frac).unwrap());
+/// Versions of the game (`base`) and of the other mods in the data folder.
+/// The `core` mod is a part of the game and is left out.
+fn read_stamp() -> Result<Stamp, io::Error> {
+    let mut stamp = Stamp::default();
+    for entry in fs::read_dir(format!("{}{}", FACTORIO_BASE, DATA_PATH))? {
+        let path = entry?.path().join("info.json");
+        if !path.is_file() {
+            continue;
+        }
+        let mut s = String::new();
+        File::open(&path)?.read_to_string(&mut s)?;
+        match mod_info::parse(&s) {
+            Some(ref info) if info.name == "base" => stamp.game_version = Some(info.version.clone()),
+            Some(ref info) if info.name == "core" => (),
+            Some(info) => stamp.mods.push((info.name, info.version)),
+            None => println!("Skipped mod info {}: no name or version", path.display())
         }
-        sign * s.parse::<f64>().unwrap()
     }
-));
-
-named!(spaces, is_a!(" \t\r\n"));
-
-named!(ident, is_a!("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_"));
-
-named!(parse_field<(Option<String>, LuaField)>, chain!(
-    name: chain!(
-        name: call!(ident)             ~
-        call!(spaces)?                 ~
-        tag!("=")                      ~
-        call!(spaces)?                 ,
-        || {str::from_utf8(name).unwrap().to_string()}
-    )?                        ~
-    field: call!(parse_value) ,
-    || {(name, field)}
-));
-
-named!(parse_value<LuaField>, alt!(
-    call!(parse_bool)   => {|x: bool| ABool(x)} |
-    call!(parse_string) => {|x: &str| AString(x.to_string())} |
-    call!(parse_float)  => {|x: f64|  AFloat(x)} |
-    call!(parse_object) => {|x|       AnObject(box x) }
-));
+    stamp.mods.sort();
+    Ok(stamp)
+}
 
-named!(parse_object<LuaObject>, chain!(
-    tag!("{")                 ~
-    call!(spaces)?            ~
-    all_but_one: many0!(chain!(
-        field: call!(parse_field) ~
-        call!(spaces)?            ~
-        tag!(",")                 ~
-        call!(spaces)?            ,
-        || {field}
-    ))                        ~
-    last: call!(parse_field)? ~
-    call!(spaces)?            ~
-    tag!("}")                 ,
-    || {
-        if last.is_none() && all_but_one.len() == 0 {
-            return LuaObject{fields: Vec::new()};
+/// Prototype files of one kind (e.g. "recipe") of every mod in the data folder.
+/// Up to 1.1 they are in a folder (`prototypes\recipe\*.lua`), since 2.0
+/// mostly in a single file (`prototypes\recipe.lua`); both are collected.
+fn mod_prototype_files(kind: &str) -> Result<Vec<PathBuf>, io::Error> {
+    let mut mods = Vec::new();
+    for entry in fs::read_dir(format!("{}{}", FACTORIO_BASE, DATA_PATH))? {
+        let path = entry?.path();
+        if path.is_dir() && path.file_name().is_some_and(|x| x != "core") {
+            mods.push(path);
         }
-        let mut vec = all_but_one;
-        if last.is_some() {
-            vec.push(last.unwrap());
+    }
+    mods.sort();
+    let mut paths = Vec::new();
+    for dir in mods {
+        let prototypes = dir.join("prototypes");
+        let file = prototypes.join(format!("{}.lua", kind));
+        if file.is_file() {
+            paths.push(file);
+        }
+        let folder = prototypes.join(kind);
+        if folder.is_dir() {
+            paths.extend(cache::list_files(&folder, "lua")?);
         }
-        LuaObject{fields: vec}
     }
-));
+    Ok(paths)
+}
 
-named!(parse_file< Vec<LuaObject> >, chain!(
-    call!(spaces)?           ~
-    tag!("data:extend(")     ~
-    call!(spaces)?           ~
-    obj: call!(parse_object) ~
-    call!(spaces)?           ~
-    tag!(")")                ,
-    || {
-        obj.fields.iter()
-            .map(|x| match x { 
-                &(_, AnObject(box ref obj)) => obj.clone(), 
-                _ => panic!("Top level primitive found")
-            })
-            .collect()
-    }
-));
+/// Recipes of all prototype files, converted in the format of the game version.
+fn process_recipes(cache: &mut Cache, stamp: &Stamp, paths: &[PathBuf]) -> Result<Vec<String>, io::Error> {
+    let format = Format::of_game_version(stamp.game_version.as_ref().map(|x| &x[..]));
+    cache::process_files(paths, cache, &format!("recipe-{:?}", format), |path, s| convert_recipes(path, s, format))
+}
 
-fn run() -> Result<(), io::Error> {
-    let out_path = Path::new("new.data");
-    let mut out_file = File::create(&out_path)?;
-    let dir_path = format!("{}{}", FACTORIO_BASE, BASE_RECIPE_PATH);
-    let dirs = fs::read_dir(dir_path)?;
-    let mut sum = 0;
+fn run(cache: &mut Cache, out_path: &Path, locale_path: &Path) -> Result<(), io::Error> {
+    if let Some(dir) = out_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut out_file = File::create(out_path)?;
+    let stamp = read_stamp()?;
+    let paths = mod_prototype_files("recipe")?;
+    let mut recipes = Vec::new();
     let mut set = HashSet::new();
-    for dir in dirs {
-        let path_buf = dir?.path();
-        let path = path_buf.as_path();
-        let mut file = File::open(&path)?;
-        let mut s = String::new();
-        file.read_to_string(&mut s)?;
-        let file = parse_file(s.as_bytes());
-        if let Done(_, mut vec) = file {
-            sum += vec.len();
-            for elem in vec {
-                let recipe = to_recipe(elem);
-                set.insert(recipe.category.clone());
-                print_recipe(&mut out_file, &recipe);
-            }
-        } else {
+    for text in process_recipes(cache, &stamp, &paths)? {
+        for recipe in data_file::read_recipes(&text)? {
+            set.insert(recipe.category.clone());
+            recipes.push(recipe);
         }
     }
-    println!("Total: {}", sum);
+    let paths = mod_prototype_files("entity")?;
+    let mut machines = Vec::new();
+    for text in cache::process_files(&paths, cache, "machine", convert_machines)? {
+        machines.extend(data_file::read_data(&text)?.machines);
+    }
+    let paths = mod_prototype_files("item")?;
+    let mut items = Vec::new();
+    for text in cache::process_files(&paths, cache, "item", convert_items)? {
+        items.extend(data_file::read_data(&text)?.items);
+    }
+    println!("Game: {}", stamp.describe());
+    println!("Total: {}", recipes.len());
+    println!("Machines: {}", machines.len());
+    println!("Items with spoilage or weight: {}", items.len());
     println!("Categories: ");
     for s in set {
         println!("\t{}", s);
     }
+    let technologies = read_technologies(cache)?;
+    data_file::write_data(&mut out_file, &DataSet { stamp: stamp, recipes: recipes, machines: machines, items: items, technologies: technologies })?;
+    run_locales(locale_path)
+}
+
+fn read_technologies(cache: &mut Cache) -> Result<Vec<Technology>, io::Error> {
+    let paths = mod_prototype_files("technology")?;
+    let mut technologies = Vec::new();
+    let mut skipped = 0;
+    for text in cache::process_files(&paths, cache, "technology", convert_technologies)? {
+        skipped += text.lines().filter(|x| x.starts_with("# infinite")).count();
+        technologies.extend(data_file::read_data(&text)?.technologies);
+    }
+    println!("Technologies: {} (skipped without fixed unit count: {})", technologies.len(), skipped);
+    Ok(technologies)
+}
+
+fn run_locales(out_path: &Path) -> Result<(), io::Error> {
+    let mut out_file = File::create(out_path)?;
+    let dir_path = format!("{}{}", FACTORIO_BASE, BASE_LOCALE_PATH);
+    let mut langs = Vec::new();
+    for lang_dir in fs::read_dir(dir_path)? {
+        let lang_path = lang_dir?.path();
+        if !lang_path.is_dir() {
+            continue;
+        }
+        let lang = lang_path.file_name().unwrap().to_string_lossy().into_owned();
+        let mut locale = Locale::new(&lang);
+        for dir in fs::read_dir(&lang_path)? {
+            let path_buf = dir?.path();
+            if path_buf.extension().is_none_or(|x| x != "cfg") {
+                continue;
+            }
+            let mut file = File::open(&path_buf)?;
+            let mut s = String::new();
+            file.read_to_string(&mut s)?;
+            locale.parse_cfg(&s);
+        }
+        locale.resolve_references();
+        locale.write(&mut out_file)?;
+        langs.push(lang);
+    }
+    println!("Languages: {}", langs.join(", "));
     Ok(())
 }
 
-fn to_recipe(obj: LuaObject) -> Recipe {
-    let obj_str = format!("{:?}", obj);
-    let mut o_name = None;
-    let mut o_category = "crafting".to_string();
-    let mut o_products = None;
-    let mut o_ingredients = None;
-    let mut o_energy_required = 0.5;
-    let mut o_enabled = false;
-    for (opt_name, field) in obj.fields {
-        if let Some(name) = opt_name {
-            match name.as_ref() {
-                "type" => {
-                    if let AString(s) = field {
-                        assert_eq!(s, "recipe");
-                    } else {
-                        panic!("\"type\" is wrong: {:?}", field);
-                    }
-                },
-                "name" => {
-                    if let AString(s) = field {
-                        o_name = Some(s);
-                    } else {
-                        panic!("\"name\" of inappropriate type: {:?} ({})", field, obj_str);
-                    }
-                },
-                "category" => {
-                    if let AString(s) = field {
-                        o_category = s;
-                    } else {
-                        panic!("\"category\" of inappropriate type: {:?} ({})", field, obj_str);
-                    }
-                },
-                "enabled" => {
-                    if let ABool(x) = field {
-                        o_enabled = x;
-                    } else if let AString(ref s) = field {
-                        o_enabled = match s.as_ref() {
-                            "true" => true,
-                            "false" => false,
-                            &_ => panic!("Try to convert string to bool, but failed: {:?} ({})", field, obj_str)
-                        };
-                    } else {
-                        panic!("\"enabled\" of inappropriate type: {:?} ({})", field, obj_str);
-                    }
-                }
-                "energy_required" => {
-                    if let AFloat(x) = field {
-                        o_energy_required = x;
-                    } else {
-                        panic!("\"energy_required\" of inappropriate type: {:?} ({})", field, obj_str);
-                    }
-                },
-                "result" => {
-                    if let AString(s) = field {
-                        o_products = Some(vec![RecipeComponent {
-                            a_type: "item".to_string(),
-                            name: s,
-                            amount: 1.0
-                        }]);
-                    } else {
-                        panic!("\"result\" of inappropriate type: {:?} ({})", field, obj_str);
-                    }
-                },
-                "result_count" => {
-                    if let AFloat(x) = field {
-                        if let &mut Some(ref mut vec) = &mut o_products {
-                            vec[0].amount = x;
-                        }
-                    } else {
-                        panic!("\"result_count\" of inappropriate type: {:?} ({})", field, obj_str);
-                    }
-                },
-                "results" => {
-                    if let AnObject(box obj) = field {
-                        o_products = Some(to_components(obj));
-                    } else {
-                        panic!("\"results\" of inappropriate type: {:?} ({})", field, obj_str);
-                    }
-                },
-                "ingredients" => {
-                    if let AnObject(box obj) = field {
-                        o_ingredients = Some(to_components(obj));
-                    } else {
-                        panic!("\"ingredients\" of inappropriate type: {:?} ({})", field, obj_str);
-                    }
-                },
-                &_ => ()
+/// Parses every prototype file, writes it back to Lua and parses the result
+/// again, reporting files where the two parsed trees differ.
+fn run_roundtrip() -> Result<(), io::Error> {
+    let mut checked = 0;
+    let mut failed = 0;
+    for kind in &["recipe", "technology"] {
+        for path_buf in mod_prototype_files(kind)? {
+            let mut file = File::open(&path_buf)?;
+            let mut s = String::new();
+            file.read_to_string(&mut s)?;
+            let objects = match lua::parse_file(&s) {
+                Ok(objects) => objects,
+                Err(_) => continue
             };
+            checked += 1;
+            let source = lua_writer::to_lua_source(&objects);
+            if lua::parse_file(&source) != Ok(objects) {
+                failed += 1;
+                println!("Round trip failed: {}", path_buf.display());
+            }
         }
     }
-    if o_name.is_none() {
-        panic!("Recipe with no name! ({})", obj_str);
-    }
-    if o_products.is_none() {
-        panic!("Recipe with no products! ({})", obj_str);
-    }
-    if o_ingredients.is_none() {
-        panic!("Recipe with no ingredients! ({})", obj_str);
-    }
-    Recipe {
-        name: o_name.unwrap(),
-        category: o_category,
-        products: o_products.unwrap(),
-        ingredients: o_ingredients.unwrap(),
-        energy_required: o_energy_required,
-        enabled: o_enabled
+    println!("Round trip: {} files checked, {} failed", checked, failed);
+    Ok(())
+}
+
+/// Collects all prototype files in `dir` and its subdirectories.
+fn find_prototype_files(dir: &Path, out: &mut Vec<PathBuf>) -> Result<(), io::Error> {
+    for entry in fs::read_dir(dir)? {
+        let path_buf = entry?.path();
+        if path_buf.is_dir() {
+            find_prototype_files(&path_buf, out)?;
+        } else if path_buf.extension().is_some_and(|x| x == "lua") {
+            out.push(path_buf);
+        }
     }
+    Ok(())
 }
 
-fn to_components(obj: LuaObject) -> Vec<RecipeComponent> {
-    let mut vec = Vec::new();
-    for obj in obj.fields {
-        if let (_, AnObject(mut obj)) = obj {
-            let mut some = false;
-            let mut none = false;
-            for i in 0..obj.fields.len() {
-                match obj.fields[i] {
-                    (Some(_), _) => some = true,
-                    (None, _) => none = true
-                }
-            }
-            if some && none {
-                panic!("Both named and unnamed fields: {:?}", obj);
-            }
-            if some {
-                let mut m: HashMap<_, _> = HashMap::new();
-                for (name, field) in obj.fields {
-                    m.insert(name.unwrap(), field);
-                }
-                vec.push(RecipeComponent {
-                    a_type: from_string(m.remove("type").unwrap()),
-                    name: from_string(m.remove("name").unwrap()),
-                    amount: from_float(m.remove("amount").unwrap())
-                });
-            } else {
-                vec.push(RecipeComponent {
-                    a_type: "item".to_string(),
-                    name: from_string(mem::replace(&mut obj.fields[0], (None, ABool(false))).1),
-                    amount: from_float(mem::replace(&mut obj.fields[1], (None, ABool(false))).1)
-                });
+/// Parses all prototype files in `dir` and its subdirectories in parallel.
+/// Files that can't be parsed are reported and skipped.
+fn load_prototypes(dir: &Path) -> Result<Vec<lua::LuaObject<'static>>, io::Error> {
+    let mut paths = Vec::new();
+    find_prototype_files(dir, &mut paths)?;
+    let parsed = cache::map_parallel(&paths, |path| -> Result<Vec<lua::LuaObject<'static>>, io::Error> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        match lua::parse_file(&s) {
+            Ok(objects) => Ok(objects.into_iter().map(|x| x.into_owned()).collect()),
+            Err(why) => {
+                println!("Skipped file {}:{}", path.display(), why);
+                Ok(Vec::new())
             }
-        } else {
-            panic!("Primitives on top level of components: {:?}", obj);
         }
+    });
+    let mut out = Vec::new();
+    for objects in parsed {
+        out.extend(objects?);
     }
-    vec
+    Ok(out)
 }
 
-fn print_recipe(file: &mut File, recipe: &Recipe) {
-    if recipe.products.len() > 1 {
-        return;
-    }
-    let ref product = recipe.products[0];
-    write!(file, "{}\t{}\t{}\t\"", product.name, product.amount, recipe.energy_required);
-    for i in 0..recipe.ingredients.len() {
-        if i > 0 {
-            write!(file, " | ");
+fn run_query(path: &str) -> Result<(), io::Error> {
+    let prototypes = load_prototypes(Path::new(&format!("{}{}", FACTORIO_BASE, BASE_PROTOTYPES_PATH)))?;
+    let results = match query::query(&prototypes, path) {
+        Ok(results) => results,
+        Err(why) => {
+            println!("Query error: {}", why);
+            return Ok(());
         }
-        let ref comp = recipe.ingredients[i];
-        write!(file, "{} x {}", comp.amount, comp.name);
+    };
+    for result in &results {
+        let mut value = String::new();
+        match result.node {
+            query::Node::Prototype(obj) => lua_writer::write_object(&mut value, obj, 0),
+            query::Node::Field(field) => lua_writer::write_value(&mut value, field, 0)
+        }
+        println!("{} = {}", result.path, value);
     }
-    writeln!(file, "\"");
+    println!("Found: {}", results.len());
+    Ok(())
 }
 
-fn from_string(field: LuaField) -> String {
-    if let AString(s) = field {
-        s
-    } else {
-        panic!("Not a string: {:?}", field);
-    }
+/// Fetches everything. Cache entries of files that are gone are dropped.
+fn run_full(cache: &mut Cache, out_path: &Path, locale_path: &Path) -> Result<(), io::Error> {
+    run(cache, out_path, locale_path)?;
+    cache.prune();
+    Ok(())
 }
 
-fn from_float(field: LuaField) -> f64 {
-    if let AFloat(x) = field {
-        x
-    } else {
-        panic!("Not a float: {:?}", field);
+/// Lints the recipes of all prototype files, reporting duplicates by file.
+fn run_check(cache: &mut Cache) -> Result<(), io::Error> {
+    let stamp = read_stamp()?;
+    let paths = mod_prototype_files("recipe")?;
+    let data_path = PathBuf::from(format!("{}{}", FACTORIO_BASE, DATA_PATH));
+    let mut files = Vec::new();
+    for (path, text) in paths.iter().zip(process_recipes(cache, &stamp, &paths)?) {
+        let name = path.strip_prefix(&data_path).unwrap_or(path).to_string_lossy().into_owned();
+        files.push((name, data_file::read_recipes(&text)?));
     }
+    let issues = check::check(&files, &MachinePreferences::new());
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    println!("Issues: {}", issues.len());
+    Ok(())
+}
+
+/// Runs `f`, reusing results of unchanged files from "fetch.cache".
+fn run_cached<F>(use_cache: bool, f: F) -> Result<(), io::Error>
+    where F: FnOnce(&mut Cache) -> Result<(), io::Error>
+{
+    let cache_path = Path::new(CACHE_PATH);
+    let mut cache = if use_cache { Cache::load(cache_path)? } else { Cache::new() };
+    f(&mut cache)?;
+    println!("Files: {} parsed, {} cached", cache.misses, cache.hits);
+    cache.save(cache_path)
 }
 
+/// Usage:
+///     fetcher [--no-cache] [--profile <name>]
+///     fetcher check [--no-cache]
+///     fetcher roundtrip
+///     fetcher query <path>, e.g. recipe.rocket-part.ingredients[2].amount
+///                           or recipe[category=chemistry].name
 fn main() {
-    if let Err(err) = run() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let use_cache = !args.iter().any(|x| x == "--no-cache");
+    args.retain(|x| x != "--no-cache");
+    let (out_path, locale_path) = match args.iter().position(|x| x == "--profile") {
+        Some(i) if i + 1 < args.len() => {
+            let name = args.drain(i..i + 2).nth(1).unwrap();
+            (profiles::path(&name), profiles::locale_path(&name))
+        },
+        _ => (PathBuf::from("new.data"), PathBuf::from("locale.data"))
+    };
+    let result = match args.first().map(|s| s.as_ref()) {
+        Some("roundtrip") => run_roundtrip(),
+        Some("query") if args.len() > 1 => run_query(&args[1]),
+        Some("check") => run_cached(use_cache, run_check),
+        _ => run_cached(use_cache, |cache| run_full(cache, &out_path, &locale_path))
+    };
+    if let Err(err) = result {
         println!("{:?}", err);
     }
 }
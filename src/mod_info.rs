@@ -0,0 +1,131 @@
+//! Reading of mod `info.json` files (`data/base/info.json` gives the game version).
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Fields of `info.json` used to stamp fetched data.
+#[derive(Debug, PartialEq)]
+pub struct ModInfo {
+    pub name: String,
+    pub version: String,
+    pub factorio_version: Option<String>
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+/// Rest of a JSON string after its opening quote.
+fn string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut out = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(out),
+            '\\' => match chars.next()? {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                'r' => out.push('\r'),
+                'b' => out.push('\u{8}'),
+                'f' => out.push('\u{c}'),
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    out.push(u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32).unwrap_or('\u{fffd}'));
+                },
+                c => out.push(c)
+            },
+            c => out.push(c)
+        }
+    }
+}
+
+/// Skips a value that isn't a string, up to the `,` or closing bracket after it.
+fn skip_value(chars: &mut Peekable<Chars>) -> Option<()> {
+    let mut depth = 0;
+    while let Some(&c) = chars.peek() {
+        match c {
+            '"' => {
+                chars.next();
+                string(chars)?;
+                continue;
+            },
+            '{' | '[' => depth += 1,
+            '}' | ']' | ',' if depth == 0 => return Some(()),
+            '}' | ']' => depth -= 1,
+            _ => ()
+        }
+        chars.next();
+    }
+    None
+}
+
+/// Top-level fields of the JSON object `s` that have string values, `None`
+/// if `s` isn't an object. Nested objects and arrays are skipped.
+fn string_fields(s: &str) -> Option<Vec<(String, String)>> {
+    let mut chars = s.chars().peekable();
+    skip_whitespace(&mut chars);
+    if chars.next()? != '{' {
+        return None;
+    }
+    let mut fields = Vec::new();
+    loop {
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            '}' => return Some(fields),
+            ',' => (),
+            '"' => {
+                let key = string(&mut chars)?;
+                skip_whitespace(&mut chars);
+                if chars.next()? != ':' {
+                    return None;
+                }
+                skip_whitespace(&mut chars);
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    fields.push((key, string(&mut chars)?));
+                } else {
+                    skip_value(&mut chars)?;
+                }
+            },
+            _ => return None
+        }
+    }
+}
+
+pub fn parse(s: &str) -> Option<ModInfo> {
+    let fields = string_fields(s)?;
+    let field = |key| fields.iter().find(|x| x.0 == key).map(|x| x.1.clone());
+    Some(ModInfo {
+        name: field("name")?,
+        version: field("version")?,
+        factorio_version: field("factorio_version")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_top_level_fields_only() {
+        let s = "{\n  \"title\": \"Space \\\"Age\\\"\",\n  \"description\": \"\\\"name\\\": \\\"quoted\\\"\",\n  \
+                 \"dependencies\": [\"base >= 2.0\", {\"name\": \"listed\"}],\n  \"package\": {\"name\": \"nested\", \"size\": 2},\n  \
+                 \"name\": \"space-age\",\n  \"version\": \"2.0.55\",\n  \"factorio_version\": \"2.0\"\n}\n";
+        assert_eq!(parse(s), Some(ModInfo {
+            name: "space-age".to_string(),
+            version: "2.0.55".to_string(),
+            factorio_version: Some("2.0".to_string())
+        }));
+        let base = parse("{\"name\":\"base\",\"version\":\"1.1.110\"}").unwrap();
+        assert_eq!((&base.name[..], base.factorio_version), ("base", None));
+    }
+
+    #[test]
+    fn rejects_files_without_name_or_version() {
+        assert_eq!(parse("{\"package\": {\"name\": \"a\", \"version\": \"1.0\"}}"), None);
+        assert_eq!(parse("{\"name\": \"a\", \"version\": 1}"), None);
+        assert_eq!(parse("[\"name\", \"version\"]"), None);
+        assert_eq!(parse("{\"name\": \"a\", \"version\": \"1.0\""), None);
+    }
+}
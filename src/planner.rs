@@ -1,203 +1,776 @@
+#![allow(unknown_lints)]
+#![allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes, clippy::needless_borrowed_reference)]
+
+extern crate factorio_recipes_planner;
+
+use std::env;
+use std::process;
 use std::path::Path;
 use std::fs::File;
-use std::error::Error;
 use std::io::prelude::*;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
+use factorio_recipes_planner::{structs, research, quality, recycling, spoilage, surfaces, builtin, data_file, diff, check, profiles, prototypes, lua_writer};
+use factorio_recipes_planner::research::ResearchPlan;
+use factorio_recipes_planner::quality::{ModuleSlot, Quality, QualitySetup};
+use factorio_recipes_planner::locale::Locale;
+use factorio_recipes_planner::preferences::{self, MachinePreferences};
+use factorio_recipes_planner::rational::{self, Rational};
+use factorio_recipes_planner::solver::*;
 
-#[derive(Debug)]
-struct Recipe {
-    output: f32,
-    time: f32,
-    compounds: Vec<(f32, String)>,
-    time_factor: f32
+/// Reports a problem of the input the planner can't go on with and exits.
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1)
 }
 
-#[derive(Debug)]
-struct Edge {
-    from: String,
-    to: String,
-    weight: f32
+fn usage(line: &str) -> ! {
+    fail(&format!("usage: {}", line))
 }
 
-#[derive(Debug)]
-struct Graph {
-    vertices: HashSet<String>,
-    edges: Vec<Edge>
+fn create_file(name: &str) -> File {
+    match File::create(name) {
+        Ok(file) => file,
+        Err(why) => fail(&format!("error while create file {}: {}", name, why))
+    }
 }
 
-#[derive(Debug)]
-struct Param {
-    time: Option<f32>,
-    rate: f32
+fn read_file(name: &str) -> String {
+    let path = Path::new(name);
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(why) => fail(&format!("error while open file {}: {}", name, why))
+    };
+    let mut s = String::new();
+    if let Err(why) = file.read_to_string(&mut s) {
+        fail(&format!("error while read from file {}: {}", name, why))
+    };
+    s
 }
 
-#[derive(Debug)]
-struct AssemblePlan {
-    out: Vec<(String, Param)>,
-    compounds: HashMap<String, Param>
+fn read_recipes(name: &str) -> Vec<structs::Recipe> {
+    read_data(name).recipes
 }
 
-impl Graph {
-    fn new() -> Graph {
-        Graph { vertices: HashSet::new(), edges: Vec::new() }
+/// Reads a data file, `profile:<name>` names a profile
+/// and `builtin:<game version>` a built-in data set.
+fn read_data(name: &str) -> data_file::DataSet {
+    if let Some(version) = name.strip_prefix("builtin:") {
+        return match builtin::data_set(version) {
+            Some(data) => data,
+            None => fail(&format!("no built-in data for game version {}", version))
+        };
     }
-}
-
-fn build_dependency_net(dependencies: &HashMap<&str, Recipe>, target: String) -> Graph {
-    let mut graph = Graph::new();
-    let mut queue = VecDeque::new();
-    queue.push_back(target);
-    while !queue.is_empty() {
-        let v = queue.pop_front().unwrap();
-        if graph.vertices.contains(&v) {
-            continue;
-        }
-        graph.vertices.insert(v.clone());
-        let recipe = match dependencies.get(&*v) {
-            Some(a) => a,
-            None => continue
+    if let Some(profile) = name.strip_prefix("profile:") {
+        return match profiles::load(profile) {
+            Ok(Some(data)) => data,
+            Ok(None) => fail(&format!("unknown profile {}", profile)),
+            Err(why) => fail(&format!("error while reading profile {}: {}", profile, why))
         };
-        for &(w, ref to) in recipe.compounds.iter() {
-            graph.edges.push(Edge { from: v.clone(), to: to.clone(), weight: w });
-            queue.push_back(to.clone());
-        }
     }
-    graph
+    match data_file::read_data(&read_file(name)) {
+        Ok(data) => data,
+        Err(why) => fail(&format!("error while parsing {}: {}", name, why))
+    }
 }
 
-fn make_plan(dependencies: &HashMap<&str, Recipe>, target: String, target_rate: f32, graph: Graph) -> AssemblePlan {
-    let mut plan: HashMap<String, Param> = HashMap::new();
-    let mut out: Vec<(String, Param)> = Vec::new();
-    let mut undone = HashSet::new();
-    for s in graph.vertices.iter() {
-        undone.insert(s.clone());
+fn temperature_range(min: Option<Rational>, max: Option<Rational>) -> String {
+    let bound = |t: Option<Rational>| t.map(|t| t.to_string()).unwrap_or_default();
+    match (min, max) {
+        (None, None) => String::new(),
+        (Some(a), Some(b)) if a == b => format!(" [{}°]", a),
+        _ => format!(" [{}..{}°]", bound(min), bound(max))
     }
+}
 
-    while !undone.is_empty() {
-        let mut v = None;
-        for s in &undone {
-            if !undone.contains(s) {
-                break;
+/// Prints the plan for `targets`; a rocket takes `rocket_parts` parts.
+fn print_plan(m: &HashMap<String, ProductRecipe>, targets: &HashMap<String, Rational>, rocket_parts: Rational, locale: &Locale) {
+    let fluids = fluid_names(m);
+    let graph = build_dependency_net(m, targets.keys().cloned().collect());
+    println!("Components:");
+    for component in &graph.vertices {
+        let kind = if fluids.contains(component) { " (fluid)" } else { "" };
+        println!("    {}{}", locale.display(component), kind);
+    }
+    println!("Edges:");
+    for &Edge { ref from, ref to, weight, min_temperature, max_temperature } in graph.edges.iter() {
+        println!("    {} --{}--> {}{}", locale.display(from), weight, locale.display(to), temperature_range(min_temperature, max_temperature));
+    }
+    let plan = make_plan(m, targets, graph);
+    let errors = machine_errors(m, &plan);
+    if !errors.is_empty() {
+        fail(&errors.join("\nerror: "));
+    }
+    println!("Assemble plan:");
+    for &(ref component, Param{time, rate}) in &plan.out{
+        if let Some(time) = time {
+            let machine = m.get(component).and_then(|r| r.machine.as_ref().ok()).map(|x| format!(" {}", x)).unwrap_or_default();
+            if fluids.contains(component) {
+                let temperature = m.get(component).and_then(|r| r.temperature);
+                println!("    {}{}: (time = {}) * (rate = {} units/s) = (count = {}{}) ({})", locale.display(component), temperature_range(temperature, temperature), time, rate, time * rate, machine, build_count(time * rate));
+            } else {
+                println!("    {}: (time = {}) * (rate = {} ({} parts/min)) = (count = {}{}) ({})", locale.display(component), time, rate, rate * 60, time * rate, machine, build_count(time * rate));
             }
-            let mut flag = true;
-            for edge in &graph.edges {
-                if edge.to == *s && undone.contains(&edge.from) {
-                    flag = false;
-                    break;
-                }
+        }
+    }
+    // Furnaces of a row can share belts of different ores, they pick the recipe.
+    let furnace_steps: Vec<_> = plan.out.iter().filter(|&&(ref c, ref p)| {
+        p.time.is_some() && m.get(c).is_some_and(|r| structs::picks_recipe_from_input(&r.category))
+    }).collect();
+    if !furnace_steps.is_empty() {
+        println!("Furnaces (recipe picked from input):");
+        let mut total = Rational::ZERO;
+        let mut separate = Rational::ZERO;
+        for &&(ref component, Param{time, rate}) in &furnace_steps {
+            let count = time.unwrap_or_default() * rate;
+            total += count;
+            separate += count.ceil();
+            println!("    {}: count = {} ({} furnaces)", locale.display(component), count, count.ceil());
+        }
+        // Pooling only saves furnaces if they share input lanes.
+        println!("    total: {} furnaces with a row per recipe, {} if rows take mixed input lanes (count = {})",
+            separate, total.ceil(), total);
+    }
+    let silo_steps: Vec<_> = plan.out.iter().filter(|&&(ref c, ref p)| {
+        p.time.is_some() && structs::fixed_recipe_machine(c).is_some_and(|x| x.name == structs::ROCKET_SILO.name)
+    }).collect();
+    if !silo_steps.is_empty() {
+        println!("Rocket silos ({} parts per rocket, {} s launch):", rocket_parts, structs::ROCKET_LAUNCH_TIME);
+        for &&(ref component, Param{rate, ..}) in &silo_steps {
+            let (rockets, silos) = rocket_silos(rate, m[component].time, rocket_parts);
+            println!("    {}: {} rockets/hour, count = {}", locale.display(component), rockets, silos);
+        }
+    }
+    println!("Components flow rate:");
+    for &(ref component, Param{time, rate}) in &plan.out{
+        if time.is_none() {
+            if fluids.contains(component) {
+                let source = fluid_sources(component, rate).map(|(name, count)| format!("{}: {}, ", name, count)).unwrap_or_default();
+                println!("    {}: rate = {} units/s ({}{})", locale.display(component), rate, source, fluid_logistics(rate));
+            } else {
+                println!("    {}: rate = {} parts/min", locale.display(component), rate * 60);
             }
-            if flag {
-                v = Some(s.clone());
-                break;
+        }
+    }
+    let byproducts = byproduct_rates(m, &plan);
+    if !byproducts.is_empty() {
+        println!("Byproducts:");
+        for &(ref component, rate, a_type) in &byproducts {
+            match a_type {
+                ComponentType::Fluid => println!("    {}: rate = {} units/s", locale.display(component), rate),
+                ComponentType::Item => println!("    {}: rate = {} parts/min", locale.display(component), rate * 60)
             }
         }
-        match v {
-            None => panic!("No leaf found with nonempty unevaluated state!"),
-            Some(v) => {
-                undone.remove(&v);
-                let time = dependencies.get(&*v).map(|r| r.time / r.time_factor);
-                let mut rate = 0.0;
-                for edge in &graph.edges {
-                    if edge.to == v {
-                        rate += edge.weight * plan.get(&edge.from).unwrap().rate;
-                    }
-                }
-                if v == target {
-                    rate = target_rate;
-                }
-                plan.insert(v.clone(), Param{time: time, rate: rate});
-                out.push((v, Param{time: time, rate: rate}));
+    }
+    let random_steps: Vec<_> = plan.out.iter().filter(|&&(ref c, ref p)| {
+        p.time.is_some() && m.get(c).is_some_and(|r| r.output_variance > Rational::ZERO)
+    }).collect();
+    if !random_steps.is_empty() {
+        println!("Output variance (expected yields are used above):");
+        for &&(ref component, Param{rate, ..}) in &random_steps {
+            let variance = rate * 60 * m[component].output_variance;
+            // The square root is mostly irrational, so the std dev is approximate.
+            let std_dev = Rational::approximate(variance.to_f64().sqrt());
+            println!("    {}: variance = {} per minute, std dev = {} parts/min (3 sigma buffer = {})",
+                locale.display(component), variance, std_dev, (std_dev * 3).ceil());
+        }
+    }
+    let fluid_steps: Vec<_> = plan.out.iter().filter(|&&(ref c, ref p)| p.time.is_some() && fluids.contains(c)).collect();
+    if !fluid_steps.is_empty() {
+        println!("Fluid logistics:");
+        for &&(ref component, Param{rate, ..}) in &fluid_steps {
+            println!("    {}: {}", locale.display(component), fluid_logistics(rate));
+        }
+    }
+}
+
+/// Whole machines to build for `count` and the utilization of the last one.
+fn build_count(count: Rational) -> String {
+    let (machines, last) = whole_machines(count);
+    format!("build {}, last at {}", machines, percent(last))
+}
+
+/// Keeps `targets` if every step's last machine is at least `min_utilization`
+/// busy, else finds the target rates closest to them at which every count is
+/// whole, and prints the plan for them.
+fn print_whole_machines(m: &HashMap<String, ProductRecipe>, targets: &HashMap<String, Rational>, min_utilization: Rational, locale: &Locale) {
+    let plan = make_plan(m, targets, build_dependency_net(m, targets.keys().cloned().collect()));
+    let counts: Vec<Rational> = plan.out.iter().filter_map(|x| x.1.time.map(|time| time * x.1.rate)).collect();
+    println!("Whole machines (last machine at least {} busy):", percent(min_utilization));
+    let scale = match whole_machine_scale(&counts, min_utilization) {
+        Some(scale) => scale,
+        None => {
+            println!("    no target rate up to {} times the given one", MAX_SCALE);
+            return;
+        }
+    };
+    let scaled: HashMap<String, Rational> = targets.iter().map(|(name, &rate)| (name.clone(), rate * scale)).collect();
+    for (name, &rate) in targets {
+        println!("    {}: rate = {} -> {} parts/min (x{})", locale.display(name), rate * 60, scaled[name] * 60, scale);
+    }
+    let plan = make_plan(m, &scaled, build_dependency_net(m, scaled.keys().cloned().collect()));
+    for &(ref component, Param{time, rate}) in &plan.out {
+        if let Some(time) = time {
+            println!("    {}: count = {} ({})", locale.display(component), time * rate, build_count(time * rate));
+        }
+    }
+}
+
+/// Prints the steps of `plan` whose rates differ from `base`.
+fn print_rate_changes(base: &AssemblePlan, plan: &AssemblePlan, locale: &Locale) {
+    for &(ref component, Param{time, rate}) in &plan.out {
+        let old = base.compounds[component].rate;
+        if old != rate {
+            let count = time.map(|time| format!(" (count = {} -> {})", time * old, time * rate)).unwrap_or_default();
+            println!("    {}: rate = {} -> {} parts/min{}", locale.display(component), old * 60, rate * 60, count);
+        }
+    }
+}
+
+/// Prints how spoiling items of the plan age between machines, what spoils
+/// and the steps that have to make more because of it.
+fn print_spoilage(m: &HashMap<String, ProductRecipe>, items: &[structs::Item], targets: &HashMap<String, Rational>,
+                  logistics: spoilage::Logistics, min_freshness: Rational, locale: &Locale) {
+    let base = make_plan(m, targets, build_dependency_net(m, targets.keys().cloned().collect()));
+    let result = spoilage::plan_with_spoilage(m, items, targets, logistics, min_freshness);
+    if result.items.is_empty() {
+        return;
+    }
+    println!("Spoilage (transit = {} s, buffer = {} s):", logistics.transit, logistics.buffer);
+    for item in &result.items {
+        println!("    {}: spoil time = {} s, spoiled {}% when made, {}% when used, {}% spoil on the way",
+            locale.display(&item.name), item.spoil_time, (item.made * 100).round(), (item.delivered * 100).round(),
+            (item.spoiled * 1000).round() / 10);
+    }
+    if !result.outputs.is_empty() {
+        println!("Spoil results:");
+        for &(ref name, rate) in &result.outputs {
+            println!("    {}: rate = {} parts/min", locale.display(name), rate * 60);
+        }
+    }
+    println!("Plan with spoiled items:");
+    print_rate_changes(&base, &result.plan, locale);
+    for warning in &result.warnings {
+        println!("Warning: {}", warning);
+    }
+}
+
+/// Prints the surfaces of the plan steps and the shipments between them.
+fn print_surfaces(m: &HashMap<String, ProductRecipe>, data: &data_file::DataSet, targets: &HashMap<String, Rational>,
+                  home: &'static surfaces::Surface, chosen: &HashMap<String, &'static surfaces::Surface>, locale: &Locale) {
+    let fluids = fluid_names(m);
+    let result = surfaces::split_plan(m, &data.recipes, &data.items, targets, home, chosen);
+    println!("Surfaces:");
+    for surface in surfaces::SURFACES {
+        let mut names: Vec<_> = result.placement.iter().filter(|x| x.1.name == surface.name).map(|x| locale.display(x.0)).collect();
+        if !names.is_empty() {
+            names.sort();
+            println!("    {}: {}", surface.name, names.join(", "));
+        }
+    }
+    if !result.shipments.is_empty() {
+        println!("Shipments:");
+        for s in &result.shipments {
+            let transport = match s.per_rocket {
+                Some(n) => format!("{} per rocket{}, {} launches/hour", n, if s.default_weight { " (default weight)" } else { "" }, s.launches_per_hour),
+                None if surfaces::find_surface(s.from).is_some_and(|x| x.is_platform) => "dropped".to_string(),
+                None => "not by rocket".to_string()
+            };
+            let rate = if fluids.contains(&s.item) { format!("{} units/s", s.rate) } else { format!("{} parts/min", s.rate * 60) };
+            println!("    {}: {} -> {}, rate = {}, {}", locale.display(&s.item), s.from, s.to, rate, transport);
+        }
+    }
+    for warning in &result.warnings {
+        println!("Warning: {}", warning);
+    }
+}
+
+/// Plans `targets` again with `surplus` items (items/s) and, with
+/// `byproducts`, the item byproducts sent to recyclers, and prints the
+/// recyclers and the steps that need less because of what they give back.
+fn print_recycling(m: &HashMap<String, ProductRecipe>, recipes: &[structs::Recipe], targets: &HashMap<String, Rational>,
+                   surplus: &[(String, Rational)], byproducts: bool, locale: &Locale) {
+    let recipes = recycling::recycling_recipes(recipes);
+    let base = make_plan(m, targets, build_dependency_net(m, targets.keys().cloned().collect()));
+    let result = recycling::plan_with_recycling(m, &recipes, targets, surplus, byproducts);
+    println!("Recycling:");
+    for r in &result.recycling {
+        println!("    {}: rate = {} parts/min, {}: count = {}", locale.display(&r.item), r.rate * 60, structs::RECYCLER.name, r.recyclers);
+        for &(ref name, rate) in &r.returns {
+            println!("        -> {}: rate = {} parts/min", locale.display(name), rate * 60);
+        }
+    }
+    println!("Plan with recycled items:");
+    print_rate_changes(&base, &result.plan, locale);
+    if !result.surplus.is_empty() {
+        println!("Surplus after recycling:");
+        for &(ref name, rate) in &result.surplus {
+            println!("    {}: rate = {} parts/min", locale.display(name), rate * 60);
+        }
+    }
+}
+
+/// Parses `<item>:<rate per minute>,...` to rates per second.
+fn parse_rates(s: &str, locale: &Locale) -> Vec<(String, Rational)> {
+    s.split(',').filter(|x| !x.is_empty()).map(|x| match x.rfind(':').map(|i| (&x[..i], Rational::from_str(&x[i + 1..]))) {
+        Some((item, Ok(rate))) => (find_name(locale, item), rate / 60),
+        _ => fail(&format!("expected <item>:<rate per minute>: {}", x))
+    }).collect()
+}
+
+fn print_research_plan(m: &HashMap<String, ProductRecipe>, plan: ResearchPlan, rocket_parts: Rational, locale: &Locale) {
+    println!("Research plan:");
+    println!("    labs: {}", plan.labs);
+    for &(ref pack, rate) in &plan.pack_rates {
+        println!("    {}: rate = {} parts/min", locale.display(pack), rate * 60);
+    }
+    let targets = plan.pack_rates.into_iter().collect();
+    print_plan(m, &targets, rocket_parts, locale);
+}
+
+/// Resolves a name given by the user, which may be either internal or localized.
+fn find_name(locale: &Locale, name: &str) -> String {
+    let names = locale.find_all(name);
+    if names.len() > 1 {
+        eprintln!("warning: {} may be any of {}, using {}", name, names.join(", "), names[0]);
+    }
+    names.into_iter().next().unwrap_or(name.to_string())
+}
+
+/// Removes the option `name` with its value from `args`.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    args.iter().position(|s| s == name).map(|i| {
+        let value: String = parse_arg(args, i + 1, name);
+        args.drain(i..i + 2);
+        value
+    })
+}
+
+/// Removes the option `name` with its numeric value from `args`.
+fn take_number<T: FromStr>(args: &mut Vec<String>, name: &str) -> Option<T> {
+    take_option(args, name).map(|value| match T::from_str(&value) {
+        Ok(x) => x,
+        Err(_) => fail(&format!("expected number for {}", name))
+    })
+}
+
+fn parse_arg<T: FromStr>(args: &[String], i: usize, name: &str) -> T {
+    match args.get(i).map(|s| T::from_str(s)) {
+        Some(Ok(x)) => x,
+        _ => fail(&format!("expected value for {}", name))
+    }
+}
+
+fn run_research(m: &HashMap<String, ProductRecipe>, data: &data_file::DataSet, args: &[String], rocket_parts: Rational, locale: &Locale) {
+    let mut spm = None;
+    let mut lab = structs::LAB.name.to_string();
+    let mut minutes = None;
+    let mut unit_time = Rational::from(30);
+    let mut bonus = Rational::ZERO;
+    let mut prerequisites = false;
+    let mut names = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_ref() {
+            "--spm" => { spm = Some(parse_arg(args, i + 1, "--spm")); i += 1; },
+            "--minutes" => { minutes = Some(parse_arg::<Rational>(args, i + 1, "--minutes")); i += 1; },
+            "--unit-time" => { unit_time = parse_arg(args, i + 1, "--unit-time"); i += 1; },
+            "--bonus" => { bonus = parse_arg(args, i + 1, "--bonus"); i += 1; },
+            "--lab" => { lab = parse_arg(args, i + 1, "--lab"); i += 1; },
+            "--prerequisites" => prerequisites = true,
+            name => names.push(find_name(locale, name))
+        }
+        i += 1;
+    }
+    let lab_speed = research::lab_speed(&data.machines, &lab).unwrap_or_else(|why| fail(&why));
+    let plan = match (spm, minutes) {
+        (Some(spm), None) => research::plan_spm(spm, &names, unit_time, lab_speed, bonus),
+        (None, Some(minutes)) => {
+            if data.technologies.is_empty() {
+                fail("no technologies in the data file, run the fetcher");
             }
+            let technologies = research::technologies(&data.technologies);
+            let queue = if prerequisites {
+                research::expand_queue(&technologies, &names, &HashSet::new()).unwrap_or_else(|why| fail(&why))
+            } else {
+                names
+            };
+            research::plan_queue(&technologies, &queue, minutes * 60, lab_speed, bonus).unwrap_or_else(|why| fail(&why))
+        },
+        _ => fail("exactly one of --spm and --minutes is expected")
+    };
+    print_research_plan(m, plan, rocket_parts, locale);
+}
+
+/// Parses a module list `<module>[:<quality>],...`, e.g. `quality-module-3:legendary,quality-module-3`.
+fn parse_modules(s: &str) -> Vec<ModuleSlot> {
+    s.split(',').filter(|x| !x.is_empty()).map(|x| {
+        let (name, quality) = match x.find(':') {
+            Some(i) => (&x[..i], Quality::from_str(&x[i + 1..]).unwrap_or_else(|why| fail(&why))),
+            None => (x, Quality::Normal)
         };
-    }
-    AssemblePlan{out: out, compounds: plan}
+        match structs::find_module(name) {
+            Some(module) => ModuleSlot { module: module, quality: quality },
+            None => fail(&format!("unknown module: {}", name))
+        }
+    }).collect()
 }
 
-fn main() {
-    let path = Path::new("new.data");
-    let mut file = match File::open(&path) {
-        Ok(file) => file,
-        Err(why) => panic!("error while open file: {}", why.description())
+/// `x` as a percentage rounded to one place; always a decimal, even with `--fractions`.
+fn percent(x: Rational) -> String {
+    format!("{}%", (x * 1000).round().to_f64() / 10.0)
+}
+
+/// Plans quality crafting of an item: ingredients for `rate` normal items per
+/// minute go through the crafting machine (and the recycling loop), and the
+/// products of the target tier or better come out.
+fn run_quality(recipes: &[structs::Recipe], args: &[String], preferences: &MachinePreferences, locale: &Locale) {
+    let mut args = args.to_vec();
+    let target = take_option(&mut args, "--target").map(|x| Quality::from_str(&x).unwrap_or_else(|why| fail(&why)))
+        .unwrap_or(Quality::Legendary);
+    let machine_quality = take_option(&mut args, "--machine-quality").map(|x| Quality::from_str(&x).unwrap_or_else(|why| fail(&why)))
+        .unwrap_or(Quality::Normal);
+    let machine_name = take_option(&mut args, "--machine");
+    let modules = take_option(&mut args, "--modules").map(|x| parse_modules(&x));
+    let recycler_modules = take_option(&mut args, "--recycler-modules").map(|x| parse_modules(&x))
+        .unwrap_or(vec![ModuleSlot { module: &structs::QUALITY_MODULE_3, quality: Quality::Normal }; structs::RECYCLER.module_slots as usize]);
+    if recycler_modules.len() > structs::RECYCLER.module_slots as usize {
+        fail(&format!("{} has {} module slots, got {} modules", structs::RECYCLER.name, structs::RECYCLER.module_slots, recycler_modules.len()));
+    }
+    let recycling = !args.iter().any(|x| x == "--no-recycling");
+    args.retain(|x| x != "--no-recycling");
+
+    let item = match args.first() {
+        Some(name) => find_name(locale, name),
+        None => fail("expected <item> [<rate per minute>]")
     };
-    let mut s = String::new();
-    if let Err(why) = file.read_to_string(&mut s) {
-        panic!("error while read from file: {}", why.description())
+    let rate: Rational = if args.len() > 1 { parse_arg(&args, 1, "rate") } else { Rational::from(60) };
+    let recipe = match recipes.iter().find(|r| r.name == item).or(recipes.iter().find(|r| r.products.iter().any(|p| p.name == item))) {
+        Some(recipe) => recipe,
+        None => fail(&format!("no recipe makes {}", item))
     };
-    let mut m: HashMap<_, _> = HashMap::new();
-    for line in s.split("\n") {
-        if line.starts_with("#") {
-            continue;
+    // The preferred machine, else the fastest one.
+    let machine = match machine_name.map(|name| structs::find_transform_machine(&name).unwrap_or_else(|| fail(&format!("unknown machine: {}", name))))
+        .or(preferences.preferred(&recipe.name, &recipe.category)) {
+        Some(machine) => machine,
+        None => match structs::machines_for_category(&recipe.category).into_iter()
+            .filter(|x| preferences.takes(x, recipe.ingredients.len()))
+            .max_by(|a, b| (a.crafting_speed, a.module_slots).partial_cmp(&(b.crafting_speed, b.module_slots)).unwrap()) {
+            Some(machine) => machine,
+            None => match preferences.choose_machine(&recipe.category, recipe.ingredients.len()) {
+                Ok(machine) => machine,
+                Err(why) => fail(&why)
+            }
         }
-        let parts: Vec<_> = line.split("\t").collect();
-        if parts.len() < 4 {
-            println!("Not enough parts: {} of 4", parts.len());
+    };
+    if !machine.crafting_categories.contains(&&recipe.category[..]) || !preferences.takes(machine, recipe.ingredients.len()) {
+        fail(&format!("{} can't craft {} ({} ingredients of category {})", machine.name, recipe.name, recipe.ingredients.len(), recipe.category));
+    }
+    let modules = modules.unwrap_or(vec![ModuleSlot { module: &structs::QUALITY_MODULE_3, quality: Quality::Normal }; machine.module_slots as usize]);
+    if modules.len() > machine.module_slots as usize {
+        fail(&format!("{} has {} module slots, got {} modules", machine.name, machine.module_slots, modules.len()));
+    }
+    let setup = QualitySetup {
+        crafting: quality::effects(&modules),
+        recycling: if recycling { Some(quality::effects(&recycler_modules)) } else { None },
+        target: target
+    };
+    let amount = match recipe.products.iter().find(|x| x.name == item) {
+        Some(product) => Rational::from_f64(product.expected_amount()),
+        None => fail(&format!("{} doesn't make {}", recipe.name, item))
+    };
+    let result = quality::quality_loop(recipe, &item, &setup).unwrap_or_else(|why| fail(&why));
+    let productivity = quality::productivity(recipe, &setup.crafting).unwrap_or_else(|why| fail(&why));
+    // crafts per minute of normal ingredients
+    let crafts = rate / amount;
+    let speed = quality::crafting_speed(machine, machine_quality, &setup.crafting);
+
+    println!("Quality plan for {} (target {}):", locale.display(&item), target);
+    println!("    machine: {} {} (speed = {}, quality chance = {}, productivity = {})", machine_quality, machine.name,
+        speed, percent(setup.crafting.quality), percent(productivity));
+    match setup.recycling {
+        Some(ref effects) => println!("    recycling: quality chance = {}", percent(effects.quality)),
+        None => println!("    recycling: off")
+    }
+    println!("Input (normal):");
+    for comp in &recipe.ingredients {
+        println!("    {}: rate = {} parts/min", locale.display(&comp.name), Rational::from_f64(comp.amount) * crafts);
+    }
+    println!("Crafts by ingredient quality:");
+    for q in quality::QUALITIES.iter().filter(|q| result.crafts[q.index()] > Rational::ZERO) {
+        println!("    {}: {} crafts/min", q, result.crafts[q.index()] * crafts);
+    }
+    println!("Products:");
+    for q in quality::QUALITIES.iter().filter(|q| result.products[q.index()] > Rational::ZERO) {
+        let note = if result.recycled[q.index()] > Rational::ZERO { " (recycled)" } else { "" };
+        println!("    {} {}: rate = {} parts/min{}", q, locale.display(&item), result.products[q.index()] * crafts, note);
+    }
+    let out = result.at_least(target) * crafts;
+    println!("Result: {} {} {} per minute ({} per normal item of input)", out, target, locale.display(&item), out / rate);
+    println!("    {}: count = {}", machine.name, result.total_crafts() * crafts / 60 * Rational::from_f64(recipe.energy_required) / speed);
+    if let Some(ref effects) = setup.recycling {
+        let recycled: Rational = result.recycled.iter().sum();
+        let time = Rational::from_f64(recycling::recycling_recipe(&item, Some(recipe)).energy_required);
+        let recycler_speed = quality::crafting_speed(&structs::RECYCLER, Quality::Normal, effects);
+        println!("    {}: count = {}", structs::RECYCLER.name, recycled * crafts / 60 * time / recycler_speed);
+    }
+}
+
+/// Reads machine preferences from `name`, or from `machines.conf` in the
+/// working directory if there is one.
+fn read_preferences(name: Option<String>) -> MachinePreferences {
+    let name = match name {
+        Some(name) => name,
+        None if Path::new(preferences::PREFERENCES_FILE).exists() => preferences::PREFERENCES_FILE.to_string(),
+        None => return MachinePreferences::new()
+    };
+    MachinePreferences::parse(&read_file(&name)).unwrap_or_else(|why| fail(&format!("error while parsing {}: {}", name, why)))
+}
+
+/// Writes the given recipes from the data file as a Lua prototype file,
+/// e.g. to patch them in a mod.
+fn export_recipes(data: &str, path: &str, names: &[String]) {
+    let recipes = read_recipes(data);
+    let objects: Vec<_> = names.iter().map(|name| {
+        match recipes.iter().find(|r| r.name == *name) {
+            Some(recipe) => prototypes::from_recipe(recipe),
+            None => fail(&format!("unknown recipe: {}", name))
+        }
+    }).collect();
+    let mut file = create_file(path);
+    if let Err(why) = file.write_all(lua_writer::to_lua_source(&objects).as_bytes()) {
+        fail(&format!("error while write to file {}: {}", path, why))
+    }
+}
+
+/// Rewrites a data file in the current format. Legacy files have no
+/// categories, they are taken from the oldest built-in data for the recipes it has.
+fn run_migrate(args: &[String]) {
+    if args.len() != 2 {
+        usage("planner migrate <old data file> <new data file>");
+    }
+    let mut data = read_data(&args[0]);
+    if Path::new(&args[0]).is_file() && data_file::is_legacy(&read_file(&args[0])) {
+        let builtin = builtin::data_set(builtin::GAME_VERSIONS[0].0).unwrap_or_default();
+        let mut unknown = 0;
+        for recipe in data.recipes.iter_mut() {
+            match builtin.recipes.iter().find(|x| x.name == recipe.name) {
+                Some(known) => recipe.category = known.category.clone(),
+                None => unknown += 1
+            }
+        }
+        if unknown > 0 {
+            eprintln!("warning: {} recipes aren't in the built-in data, their category is crafting", unknown);
+        }
+    }
+    let mut file = create_file(&args[1]);
+    if let Err(why) = data_file::write_data(&mut file, &data) {
+        fail(&format!("error while write to file {}: {}", args[1], why))
+    }
+    println!("Migrated {} recipes to format version {}", data.recipes.len(), data_file::FORMAT_VERSION);
+}
+
+/// Reads a saved plan: lines `<item> <rate per minute>`, `#` starts a comment.
+/// Returns target rates per second.
+fn read_saved_plan(name: &str, locale: &Locale) -> HashMap<String, Rational> {
+    let mut targets = HashMap::new();
+    for line in read_file(name).lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("#") {
             continue;
         }
-        let compounds: Vec<_> = parts[3].replace("\"", "").split("|").map(|s| {
-            let v: Vec<_> = s.split(" x ").collect();
-            (match f32::from_str(v[0].trim()) {
-                Ok(a) => a,
-                Err(why) => panic!("error while parsing compounds ({})[{}]: {}", s, v[0], why.description())
-            }, v[1].trim().to_string())
-        }).collect();
-        let time_factor = match compounds.len() {
-            1...2 => 0.75, // FIXME 0.5
-            3...4 => 0.75,
-            5...6 => 1.25,
-            _ => panic!("illegal number of components: {}", compounds.len())
+        let (item, rate) = match line.rfind(char::is_whitespace) {
+            Some(i) => (line[..i].trim(), &line[i + 1..]),
+            None => fail(&format!("expected <item> <rate per minute> in {}: {}", name, line))
         };
-        let mut recipe = Recipe {
-            output: match f32::from_str(parts[1]) {
-                Ok(a) => a,
-                Err(why) => {
-                    println!("Not parsed 'output' of {}[{}]: {}", parts[0], parts[1], why.description());
-                    continue;
-                }
-            },
-            time: match f32::from_str(parts[2]) {
-                Ok(a) => a,
-                Err(why) => {
-                    println!("Not parsed 'time' of {}[{}]: {}", parts[0], parts[2], why.description());
-                    continue;
-                }
-            },
-            compounds: compounds,
-            time_factor: time_factor
+        match Rational::from_str(rate) {
+            Ok(rate) => targets.insert(find_name(locale, item), rate / 60),
+            Err(why) => fail(&format!("illegal rate in {}: {}: {}", name, line, why))
         };
-        let output = recipe.output;
-        if output != 1.0 {
-            recipe.output /= output;
-            recipe.time /= output;
-            for &mut (ref mut w, _) in &mut recipe.compounds {
-                *w /= output;
-            }
+    }
+    targets
+}
+
+fn print_section(title: &str, diff: &diff::SectionDiff, locale: &Locale) {
+    if diff.is_empty() {
+        return;
+    }
+    println!("{}:", title);
+    for name in &diff.added {
+        println!("    + {}", locale.display(name));
+    }
+    for name in &diff.removed {
+        println!("    - {}", locale.display(name));
+    }
+    for changes in &diff.changed {
+        println!("    ~ {}", locale.display(&changes.name));
+        for change in &changes.changes {
+            println!("        {}", change);
         }
-        m.insert(parts[0], recipe);
     }
+}
 
-    let name = "electronic-circuit".to_string();
-    println!("{:?}", m);
-    let graph = build_dependency_net(&m, name.clone());
-    println!("Components:");
-    for component in &graph.vertices {
-        println!("    {}", component);
+/// Compares two data files and re-runs the saved plans on both.
+fn run_diff(args: &[String], preferences: &MachinePreferences, locale: &Locale) {
+    if args.len() < 2 {
+        usage("planner diff <old data file> <new data file> [<saved plan file>...]");
     }
-    println!("Edges:");
-    for &Edge { ref from, ref to, weight } in graph.edges.iter() {
-        println!("    {} --{}--> {}", from, weight, to);
+    let old = read_data(&args[0]);
+    let new = read_data(&args[1]);
+    let d = diff::diff(&old, &new);
+    if d.recipes.is_empty() && d.machines.is_empty() && d.items.is_empty() {
+        println!("No changes");
     }
-    let plan = make_plan(&m, name.clone(), 1.5 / 40.0, graph);
-    println!("Assemble plan:");
-    for &(ref component, Param{time, rate}) in &plan.out{
-        if let Some(time) = time {
-            println!("    {}: (time = {}) * (rate = {} ({} parts/min)) = (count = {})", component, time, rate, rate * 60.0, time * rate);
+    print_section("Recipes", &d.recipes, locale);
+    print_section("Machines", &d.machines, locale);
+    print_section("Items", &d.items, locale);
+    for plan in &args[2..] {
+        let changes = diff::diff_plan(&old, &new, &read_saved_plan(plan, locale), preferences);
+        println!("Plan {}:", plan);
+        if changes.is_empty() {
+            println!("    no changes of machine counts");
+        }
+        let count = |x: Option<Rational>| x.map(|x| x.to_string()).unwrap_or("-".to_string());
+        for change in &changes {
+            println!("    {}: count {} -> {}", locale.display(&change.name), count(change.old), count(change.new));
         }
     }
-    println!("Components flow rate:");
-    for &(ref component, Param{time, rate}) in &plan.out{
-        if let None = time {
-            println!("    {}: rate = {} parts/min", component, rate * 60.0);
+}
+
+fn print_profiles() {
+    let names = match profiles::list() {
+        Ok(names) => names,
+        Err(why) => fail(&format!("error while listing profiles: {}", why))
+    };
+    for name in names {
+        let data = read_data(&format!("profile:{}", name));
+        let origin = if profiles::path(&name).exists() { profiles::path(&name).display().to_string() } else { "built-in".to_string() };
+        println!("{}: {} ({} recipes, {})", name, data.stamp.describe(), data.recipes.len(), origin);
+    }
+}
+
+/// Lints the recipes of a data file.
+fn run_check(name: &str, preferences: &MachinePreferences) {
+    let data = read_data(name);
+    let preferences = preferences.clone().with_machines(&data.machines);
+    let issues = check::check(&[(name.to_string(), data.recipes)], &preferences);
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    println!("Issues: {}", issues.len());
+}
+
+/// Usage (`--lang <lang>`, `--locale <locale file>`, `--data <data file>`,
+/// `--profile <name>`, `--machines <preference file>` and `--fractions` may
+/// be added to any of them, `--game` is the same as `--profile`; data file
+/// names may be `profile:<name>` or `builtin:<version>`):
+///     planner [<item> [<rate per minute>]] [--recycle <item>:<rate per minute>,...] [--recycle-byproducts]
+///         [--transit <seconds>] [--buffer <seconds>] [--min-freshness <percent>]
+///         [--surface <surface>] [--on <item>:<surface>,...]
+///         [--whole-machines <min utilization percent>]
+///     planner migrate <old data file> <new data file>
+///     planner profiles
+///     planner check [<data file>]
+///     planner diff <old data file> <new data file> [<saved plan file>...]
+///     planner export <lua file> <recipe>...
+///     planner quality <item> [<rate per minute>] [--target <quality>] [--machine <name>]
+///         [--machine-quality <quality>] [--modules <list>] [--recycler-modules <list>] [--no-recycling]
+///     planner research --spm <n> [--unit-time <seconds>] [--bonus <x>] [--lab <name>] <pack>...
+///     planner research --minutes <n> [--bonus <x>] [--lab <name>] [--prerequisites] <technology>...
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let lang = take_option(&mut args, "--lang");
+    if args.iter().any(|x| x == "--fractions") {
+        rational::set_fractions(true);
+        args.retain(|x| x != "--fractions");
+    }
+    // An explicit data file wins, then a profile, then the fetcher output
+    // in the working directory, then the latest built-in data.
+    let profile = take_option(&mut args, "--profile").or(take_option(&mut args, "--game"));
+    let data = match (take_option(&mut args, "--data"), profile) {
+        (Some(name), _) => name,
+        (None, Some(profile)) => format!("profile:{}", profile),
+        (None, None) if Path::new("new.data").exists() => "new.data".to_string(),
+        (None, None) => format!("builtin:{}", builtin::DEFAULT_VERSION)
+    };
+    // Localized names go with the data: those of a profile, else the fetcher output.
+    let locale_file = take_option(&mut args, "--locale").unwrap_or_else(|| match data.strip_prefix("profile:") {
+        Some(profile) => profiles::locale_path(profile).display().to_string(),
+        None => "locale.data".to_string()
+    });
+    let preferences = read_preferences(take_option(&mut args, "--machines"));
+    if args.first().map(|s| s.as_ref()) == Some("migrate") {
+        run_migrate(&args[1..]);
+        return;
+    }
+    if args.first().map(|s| s.as_ref()) == Some("profiles") {
+        print_profiles();
+        return;
+    }
+    if args.first().map(|s| s.as_ref()) == Some("check") {
+        run_check(args.get(1).unwrap_or(&data), &preferences);
+        return;
+    }
+    if args.first().map(|s| s.as_ref()) == Some("export") {
+        if args.len() < 3 {
+            usage("planner export <lua file> <recipe>...");
         }
+        export_recipes(&data, &args[1], &args[2..]);
+        return;
     }
-}
\ No newline at end of file
+    let data_set = read_data(&data);
+    let preferences = preferences.with_machines(&data_set.machines);
+    let rocket_parts = Rational::from(structs::rocket_parts_required(data_set.stamp.game_version.as_deref()));
+    let m = to_product_recipes_with(&data_set.recipes, &preferences);
+    let locale = match lang {
+        Some(lang) => Locale::parse_data(&read_file(&locale_file), &lang),
+        None => Locale::new("")
+    };
+    if args.first().map(|s| s.as_ref()) == Some("quality") {
+        run_quality(&data_set.recipes, &args[1..], &preferences, &locale);
+        return;
+    }
+    if args.first().map(|s| s.as_ref()) == Some("research") {
+        run_research(&m, &data_set, &args[1..], rocket_parts, &locale);
+        return;
+    }
+    if args.first().map(|s| s.as_ref()) == Some("diff") {
+        run_diff(&args[1..], &preferences, &locale);
+        return;
+    }
+
+    let logistics = spoilage::Logistics {
+        transit: take_number(&mut args, "--transit").unwrap_or_default(),
+        buffer: take_number(&mut args, "--buffer").unwrap_or_default()
+    };
+    let min_freshness: Rational = take_number(&mut args, "--min-freshness").unwrap_or_default();
+    let home = take_option(&mut args, "--surface").map(|x| surfaces::find_surface(&x).unwrap_or_else(|| fail(&format!("unknown surface: {}", x))));
+    let chosen: HashMap<String, &'static surfaces::Surface> = take_option(&mut args, "--on").map(|x| x.split(',').filter(|x| !x.is_empty()).map(|x| {
+        match x.rfind(':').map(|i| (&x[..i], surfaces::find_surface(&x[i + 1..]))) {
+            Some((item, Some(surface))) => (find_name(&locale, item), surface),
+            _ => fail(&format!("expected <item>:<surface>: {}", x))
+        }
+    }).collect()).unwrap_or_default();
+    let min_utilization: Option<Rational> = take_number(&mut args, "--whole-machines");
+    let surplus = take_option(&mut args, "--recycle").map(|x| parse_rates(&x, &locale)).unwrap_or_default();
+    let recycle_byproducts = args.iter().any(|x| x == "--recycle-byproducts");
+    args.retain(|x| x != "--recycle-byproducts");
+    let name = args.first().map(|s| find_name(&locale, s)).unwrap_or("electronic-circuit".to_string());
+    let rate: Rational = if args.len() > 1 { parse_arg(&args, 1, "rate") } else { Rational::from(60) };
+    let mut targets = HashMap::new();
+    targets.insert(name, rate / 60);
+    print_plan(&m, &targets, rocket_parts, &locale);
+    if let Some(min_utilization) = min_utilization {
+        print_whole_machines(&m, &targets, min_utilization / 100, &locale);
+    }
+    if !surplus.is_empty() || recycle_byproducts {
+        print_recycling(&m, &data_set.recipes, &targets, &surplus, recycle_byproducts, &locale);
+    }
+    print_spoilage(&m, &data_set.items, &targets, logistics, min_freshness / 100, &locale);
+    // Split by surface if asked for or if some recipe of the plan needs it.
+    let graph = build_dependency_net(&m, targets.keys().cloned().collect());
+    let conditions = data_set.recipes.iter().any(|r| !r.surface_conditions.is_empty() && r.products.iter().any(|p| graph.vertices.contains(&p.name)));
+    if home.is_some() || !chosen.is_empty() || conditions {
+        print_surfaces(&m, &data_set, &targets, home.unwrap_or(&surfaces::NAUVIS), &chosen, &locale);
+    }
+}
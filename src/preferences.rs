@@ -0,0 +1,165 @@
+//! Machine preferences: which machine crafts the recipes of a category, with
+//! overrides for single recipes. They also hold how many ingredients each
+//! machine takes, from the machine blocks of the data file.
+//!
+//! A preference file has one line per preference, `#` starts a comment:
+//! ```text
+//! category crafting assembling-machine-2
+//! category smelting steel-furnace
+//! recipe electronic-circuit assembling-machine-3
+//! ```
+
+use std::collections::HashMap;
+use structs::{self, Entity, TransformMachine};
+
+/// Default preference file in the working directory.
+pub const PREFERENCES_FILE: &'static str = "machines.conf";
+
+#[derive(Default, Clone)]
+pub struct MachinePreferences {
+    pub categories: HashMap<String, &'static TransformMachine>,
+    pub recipes: HashMap<String, &'static TransformMachine>,
+    /// Most ingredients of the machines of the data file, `None` for any
+    /// number. Machines missing here have their built-in limit.
+    pub ingredient_limits: HashMap<String, Option<usize>>
+}
+
+impl MachinePreferences {
+    pub fn new() -> MachinePreferences {
+        MachinePreferences::default()
+    }
+
+    pub fn parse(s: &str) -> Result<MachinePreferences, String> {
+        let mut out = MachinePreferences::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<_> = line.split_whitespace().collect();
+            if parts.len() != 3 {
+                return Err(format!("line {}: expected <category|recipe> <name> <machine>", i + 1));
+            }
+            let machine = match structs::find_transform_machine(parts[2]) {
+                Some(machine) => machine,
+                None => return Err(format!("line {}: unknown machine: {}", i + 1, parts[2]))
+            };
+            match parts[0] {
+                "category" => {
+                    if !machine.crafting_categories.contains(&parts[1]) {
+                        return Err(format!("line {}: {} doesn't craft category {}", i + 1, machine.name, parts[1]));
+                    }
+                    out.categories.insert(parts[1].to_string(), machine);
+                },
+                "recipe" => { out.recipes.insert(parts[1].to_string(), machine); },
+                x => return Err(format!("line {}: expected category or recipe, got {}", i + 1, x))
+            }
+        }
+        Ok(out)
+    }
+
+    /// Takes the ingredient limits from the machine blocks of a data file,
+    /// replacing the ones taken before.
+    pub fn with_machines(mut self, machines: &[Entity]) -> MachinePreferences {
+        self.ingredient_limits = machines.iter()
+            .filter(|x| !x.crafting_categories.is_empty())
+            .map(|x| (x.name.clone(), x.ingredient_count.map(|n| n as usize)))
+            .collect();
+        self
+    }
+
+    /// Most ingredients a recipe crafted by `machine` may have, `None` for any number.
+    pub fn ingredient_limit(&self, machine: &TransformMachine) -> Option<usize> {
+        self.ingredient_limits.get(machine.name).cloned().unwrap_or(Some(machine.ingredient_count as usize))
+    }
+
+    pub fn takes(&self, machine: &TransformMachine, ingredients: usize) -> bool {
+        self.ingredient_limit(machine).is_none_or(|limit| limit >= ingredients)
+    }
+
+    /// The machine crafting a recipe of `category` with `ingredients`
+    /// ingredients: the slowest one able to, e.g. assembling-machine-1 for up
+    /// to two ingredients in Factorio 1.0, assembling-machine-2 for up to four.
+    pub fn choose_machine(&self, category: &str, ingredients: usize) -> Result<&'static TransformMachine, String> {
+        let machines = structs::machines_for_category(category);
+        if machines.is_empty() {
+            return Err(format!("no machine for category {}", category));
+        }
+        machines.iter().copied()
+            .filter(|x| self.takes(x, ingredients))
+            .min_by(|a, b| a.crafting_speed.partial_cmp(&b.crafting_speed).unwrap())
+            .ok_or_else(|| format!("no machine for {} ingredients of category {} (at most {})", ingredients, category,
+                                   machines.iter().filter_map(|x| self.ingredient_limit(x)).max().unwrap_or(0)))
+    }
+
+    /// The preferred machine of a recipe: its override, else the one of its category.
+    pub fn preferred(&self, recipe: &str, category: &str) -> Option<&'static TransformMachine> {
+        self.recipes.get(recipe).or(self.categories.get(category)).copied()
+    }
+
+    /// The machine crafting `recipe`: the fixed-recipe machine if there is
+    /// one, else the recipe's override, which must be able to craft it. The
+    /// machine of the category is used if it takes all ingredients, otherwise
+    /// the slowest machine that does.
+    pub fn machine_for(&self, recipe: &structs::Recipe) -> Result<&'static TransformMachine, String> {
+        let ingredients = recipe.ingredients.len();
+        if let Some(machine) = structs::fixed_recipe_machine(&recipe.name) {
+            return Ok(machine);
+        }
+        if let Some(&machine) = self.recipes.get(&recipe.name) {
+            if !machine.crafting_categories.contains(&&recipe.category[..]) {
+                return Err(format!("{} doesn't craft category {}", machine.name, recipe.category));
+            }
+            if let Some(limit) = self.ingredient_limit(machine).filter(|&limit| limit < ingredients) {
+                return Err(format!("{} takes at most {} ingredients, got {}", machine.name, limit, ingredients));
+            }
+            return Ok(machine);
+        }
+        match self.categories.get(&recipe.category) {
+            Some(&machine) if self.takes(machine, ingredients) => Ok(machine),
+            _ => self.choose_machine(&recipe.category, ingredients)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use structs::{self, Entity};
+    use super::*;
+
+    fn machine(name: &str, ingredient_count: Option<f64>) -> Entity {
+        Entity {
+            a_type: "assembling-machine".to_string(),
+            name: name.to_string(),
+            crafting_speed: Some(1.0),
+            crafting_categories: vec!["crafting".to_string()],
+            ingredient_count: ingredient_count,
+            researching_speed: None,
+            mining_speed: None,
+            energy_usage: None
+        }
+    }
+
+    #[test]
+    fn takes_ingredient_limits_from_the_data() {
+        let builtin = MachinePreferences::new();
+        assert_eq!(builtin.choose_machine("crafting", 3).unwrap().name, "assembling-machine-2");
+        assert!(builtin.choose_machine("crafting", 7).is_err());
+
+        let data = MachinePreferences::new().with_machines(&[machine("assembling-machine-1", None), machine("assembling-machine-2", Some(3.0))]);
+        assert_eq!(data.ingredient_limit(&structs::ASSEMBLING_MACHINE_1), None);
+        assert_eq!(data.ingredient_limit(&structs::ASSEMBLING_MACHINE_2), Some(3));
+        assert_eq!(data.ingredient_limit(&structs::ASSEMBLING_MACHINE_3), Some(6));
+        assert_eq!(data.choose_machine("crafting", 7).unwrap().name, "assembling-machine-1");
+    }
+
+    #[test]
+    fn parses_preference_files() {
+        let prefs = MachinePreferences::parse("# comment\ncategory crafting assembling-machine-2\nrecipe gear assembling-machine-3\n").unwrap();
+        assert_eq!(prefs.preferred("gear", "crafting").unwrap().name, "assembling-machine-3");
+        assert_eq!(prefs.preferred("wire", "crafting").unwrap().name, "assembling-machine-2");
+        assert!(prefs.preferred("plate", "smelting").is_none());
+        assert!(MachinePreferences::parse("category smelting assembling-machine-2").is_err());
+        assert!(MachinePreferences::parse("recipe gear no-such-machine").is_err());
+    }
+}
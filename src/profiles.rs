@@ -0,0 +1,102 @@
+//! Named data profiles, e.g. one per game version or mod set.
+//!
+//! A profile is a data file `profiles/<name>.data` in the working directory,
+//! written by `fetcher --profile <name>` along with its localized names in
+//! `profiles/<name>.locale`. Built-in data sets are profiles
+//! named after their game version; a profile file with the same name
+//! overrides them.
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use builtin;
+use data_file::{self, DataSet};
+
+pub const PROFILE_DIR: &'static str = "profiles";
+
+pub fn path(name: &str) -> PathBuf {
+    path_in(Path::new(PROFILE_DIR), name)
+}
+
+pub fn locale_path(name: &str) -> PathBuf {
+    PathBuf::from(PROFILE_DIR).join(format!("{}.locale", name))
+}
+
+fn path_in(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.data", name))
+}
+
+/// Names of all profiles: profile files first, then built-in ones.
+pub fn list() -> Result<Vec<String>, io::Error> {
+    list_in(Path::new(PROFILE_DIR))
+}
+
+fn list_in(dir: &Path) -> Result<Vec<String>, io::Error> {
+    let mut names = Vec::new();
+    match fs::read_dir(dir) {
+        Ok(entries) => for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|x| x == "data") {
+                names.push(path.file_stem().unwrap().to_string_lossy().into_owned());
+            }
+        },
+        Err(ref why) if why.kind() == io::ErrorKind::NotFound => (),
+        Err(why) => return Err(why)
+    }
+    names.sort();
+    for &(version, _) in builtin::GAME_VERSIONS.iter() {
+        if !names.iter().any(|x| x == version) {
+            names.push(version.to_string());
+        }
+    }
+    Ok(names)
+}
+
+/// Data of the profile `name`, `None` if there is no such profile.
+pub fn load(name: &str) -> Result<Option<DataSet>, io::Error> {
+    load_from(Path::new(PROFILE_DIR), name)
+}
+
+fn load_from(dir: &Path, name: &str) -> Result<Option<DataSet>, io::Error> {
+    match File::open(path_in(dir, name)) {
+        Ok(mut file) => {
+            let mut s = String::new();
+            file.read_to_string(&mut s)?;
+            data_file::read_data(&s).map(Some)
+        },
+        Err(ref why) if why.kind() == io::ErrorKind::NotFound => Ok(builtin::data_set(name)),
+        Err(why) => Err(why)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+    use super::*;
+
+    #[test]
+    fn profile_files_override_built_in_data() {
+        let dir = env::temp_dir().join(format!("planner-profiles-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(list_in(&dir).unwrap(), vec!["1.1", "2.0"]);
+        assert_eq!(load_from(&dir, "2.0").unwrap().unwrap().stamp.game_version.as_deref(), Some("2.0"));
+        assert!(load_from(&dir, "modded").unwrap().is_none());
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(path_in(&dir, "modded"), "format\t8\ngame\t2.0.55\nmod\tquality\t2.0.55\n").unwrap();
+        fs::write(path_in(&dir, "2.0"), "format\t8\ngame\t2.0.60\nrecipe\ta\nend\n").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        assert_eq!(list_in(&dir).unwrap(), vec!["2.0", "modded", "1.1"]);
+        let modded = load_from(&dir, "modded").unwrap().unwrap();
+        assert_eq!(modded.stamp.mods, vec![("quality".to_string(), "2.0.55".to_string())]);
+        let overridden = load_from(&dir, "2.0").unwrap().unwrap();
+        assert_eq!((overridden.stamp.game_version.as_deref(), overridden.recipes.len()), (Some("2.0.60"), 1));
+
+        fs::write(path_in(&dir, "broken"), "format\t99\n").unwrap();
+        assert!(load_from(&dir, "broken").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
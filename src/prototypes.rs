@@ -0,0 +1,484 @@
+//! Conversion of parsed Lua prototypes to the data model.
+//!
+//! Recipes come in two formats. The legacy one (up to 1.1) has short
+//! components `{"iron-plate", 2}`, `result`/`result_count` and separate
+//! `normal`/`expensive` difficulties. In Factorio 2.0 all components are
+//! tables with `type`, products are always `results`, and recipes gain
+//! `allow_productivity`, `maximum_productivity` and `surface_conditions`.
+//! The format is chosen by the game version, but a recipe with a legacy
+//! layout (e.g. from a mod that wasn't updated) is always read as legacy.
+
+use std::str::FromStr;
+use structs::*;
+use lua::LuaObject;
+use lua::LuaField::{self, *};
+use deserialize::{FromLua, LuaError};
+
+/// Fields of a recipe, that can be either on the top level or in `normal`
+/// (recipes with separate normal and expensive difficulty).
+struct RecipeData {
+    ingredients: Vec<RecipeComponent>,
+    result: Option<String>,
+    result_count: f64,
+    results: Option<Vec<RecipeComponent>>,
+    energy_required: f64,
+    enabled: bool
+}
+
+impl_from_lua!(RecipeData {
+    ingredients: "ingredients",
+    result: "result",
+    result_count: "result_count" => 1.0,
+    results: "results",
+    energy_required: "energy_required" => 0.5,
+    enabled: "enabled" => true
+});
+
+struct RecipePrototype {
+    a_type: String,
+    name: String,
+    category: String,
+    normal: Option<LuaObject<'static>>
+}
+
+impl_from_lua!(RecipePrototype {
+    a_type: "type",
+    name: "name",
+    category: "category" => "crafting".to_string(),
+    normal: "normal"
+});
+
+/// Recipe in the Factorio 2.0 format.
+struct Recipe2Prototype {
+    a_type: String,
+    name: String,
+    category: String,
+    ingredients: Vec<RecipeComponent>,
+    results: Vec<RecipeComponent>,
+    energy_required: f64,
+    enabled: bool,
+    allow_productivity: bool,
+    maximum_productivity: f64,
+    surface_conditions: Vec<SurfaceCondition>
+}
+
+impl_from_lua!(Recipe2Prototype {
+    a_type: "type",
+    name: "name",
+    category: "category" => "crafting".to_string(),
+    ingredients: "ingredients",
+    results: "results",
+    energy_required: "energy_required" => 0.5,
+    enabled: "enabled" => true,
+    allow_productivity: "allow_productivity" => false,
+    maximum_productivity: "maximum_productivity" => 3.0,
+    surface_conditions: "surface_conditions"
+});
+
+impl_from_lua!(SurfaceCondition {
+    property: "property",
+    min: "min",
+    max: "max"
+});
+
+/// Named form of a recipe component: `{type="fluid", name="water", amount=10}`.
+struct ComponentPrototype {
+    a_type: String,
+    name: String,
+    amount: Option<f64>,
+    amount_min: Option<f64>,
+    amount_max: Option<f64>,
+    probability: f64,
+    temperature: Option<f64>,
+    minimum_temperature: Option<f64>,
+    maximum_temperature: Option<f64>
+}
+
+impl_from_lua!(ComponentPrototype {
+    a_type: "type" => "item".to_string(),
+    name: "name",
+    amount: "amount",
+    amount_min: "amount_min",
+    amount_max: "amount_max",
+    probability: "probability" => 1.0,
+    temperature: "temperature",
+    minimum_temperature: "minimum_temperature",
+    maximum_temperature: "maximum_temperature"
+});
+
+fn item_component(name: String, amount: f64) -> RecipeComponent {
+    RecipeComponent {
+        a_type: "item".to_string(),
+        name: name,
+        amount: amount,
+        amount_min: None,
+        amount_max: None,
+        probability: 1.0,
+        temperature: None,
+        minimum_temperature: None,
+        maximum_temperature: None
+    }
+}
+
+/// Components are either named tables or short arrays `{"iron-plate", 2}`.
+impl FromLua for RecipeComponent {
+    fn from_lua(field: &LuaField, path: &str) -> Result<RecipeComponent, LuaError> {
+        let obj = ::deserialize::as_object(field, path)?;
+        let named = obj.fields.iter().filter(|x| x.0.is_some()).count();
+        if named > 0 && named < obj.fields.len() {
+            return Err(LuaError::new(path, "both named and unnamed fields".to_string()));
+        }
+        if named == 0 {
+            if obj.fields.len() != 2 {
+                return Err(LuaError::new(path, format!("expected {{name, amount}}, found {} fields", obj.fields.len())));
+            }
+            return Ok(item_component(
+                String::from_lua(&obj.fields[0].1, &format!("{}[1]", path))?,
+                f64::from_lua(&obj.fields[1].1, &format!("{}[2]", path))?
+            ));
+        }
+        let c = ComponentPrototype::from_lua(field, path)?;
+        let amount = match (c.amount, c.amount_min, c.amount_max) {
+            (Some(amount), _, _) => amount,
+            (None, Some(min), Some(max)) => (min + max) / 2.0,
+            _ => return Err(LuaError::new(&format!("{}.amount", path), "missing field".to_string()))
+        };
+        Ok(RecipeComponent {
+            a_type: c.a_type,
+            name: c.name,
+            amount: amount,
+            amount_min: c.amount_min,
+            amount_max: c.amount_max,
+            probability: c.probability,
+            temperature: c.temperature,
+            minimum_temperature: c.minimum_temperature,
+            maximum_temperature: c.maximum_temperature
+        })
+    }
+}
+
+struct TechnologyUnit {
+    count: Option<f64>,
+    time: f64,
+    ingredients: Vec<RecipeComponent>
+}
+
+impl_from_lua!(TechnologyUnit {
+    count: "count",
+    time: "time",
+    ingredients: "ingredients"
+});
+
+struct TechnologyPrototype {
+    a_type: String,
+    name: String,
+    unit: TechnologyUnit,
+    prerequisites: Vec<String>
+}
+
+impl_from_lua!(TechnologyPrototype {
+    a_type: "type",
+    name: "name",
+    unit: "unit",
+    prerequisites: "prerequisites"
+});
+
+impl_from_lua!(Item {
+    a_type: "type",
+    name: "name",
+    stack_size: "stack_size",
+    subgroup: "subgroup",
+    place_result: "place_result",
+    fuel_value: "fuel_value",
+    spoil_ticks: "spoil_ticks",
+    spoil_result: "spoil_result",
+    weight: "weight"
+});
+
+impl_from_lua!(Entity {
+    a_type: "type",
+    name: "name",
+    crafting_speed: "crafting_speed",
+    crafting_categories: "crafting_categories",
+    ingredient_count: "ingredient_count",
+    researching_speed: "researching_speed",
+    mining_speed: "mining_speed",
+    energy_usage: "energy_usage"
+});
+
+/// Path of a prototype in error messages, e.g. `recipe.rocket-part`.
+fn prototype_path(obj: &LuaObject) -> String {
+    let get = |key| match obj.get(key) {
+        Some(&AString(ref s)) => s.to_string(),
+        _ => "?".to_string()
+    };
+    format!("{}.{}", get("type"), get("name"))
+}
+
+/// Deserializes a whole prototype into any `FromLua` type.
+pub fn from_prototype<T: FromLua>(obj: LuaObject) -> Result<T, LuaError> {
+    let path = prototype_path(&obj);
+    T::from_lua(&AnObject(Box::new(obj)), &path)
+}
+
+fn check_type(a_type: &str, expected: &str, path: &str) -> Result<(), LuaError> {
+    if a_type != expected {
+        return Err(LuaError::new(&format!("{}.type", path), format!("expected \"{}\", found \"{}\"", expected, a_type)));
+    }
+    Ok(())
+}
+
+/// Prototype format, see the module documentation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Legacy,
+    Factorio2
+}
+
+impl Format {
+    /// Format of the prototypes of a game version, legacy if it is unknown.
+    pub fn of_game_version(version: Option<&str>) -> Format {
+        match version.and_then(|x| x.split('.').next()).map(u32::from_str) {
+            Some(Ok(major)) if major >= 2 => Format::Factorio2,
+            _ => Format::Legacy
+        }
+    }
+}
+
+impl Format {
+    /// Format of a single recipe: legacy if it has fields or short components
+    /// only the legacy format has, else `format`.
+    pub fn of_recipe(obj: &LuaObject, format: Format) -> Format {
+        let short = |key| match obj.get(key) {
+            Some(&AnObject(ref list)) => list.fields.iter().any(|x| match x.1 {
+                AnObject(ref c) => c.fields.iter().all(|f| f.0.is_none()),
+                _ => false
+            }),
+            _ => false
+        };
+        let legacy_keys = ["normal", "expensive", "result", "result_count"];
+        if legacy_keys.iter().any(|x| obj.get(x).is_some()) || short("ingredients") || short("results") {
+            Format::Legacy
+        } else {
+            format
+        }
+    }
+}
+
+/// Converts a recipe prototype. Returns `Ok(None)` for recipes that don't
+/// exist in normal difficulty (`normal = false`).
+pub fn to_recipe(obj: LuaObject, format: Format) -> Result<Option<Recipe>, LuaError> {
+    match Format::of_recipe(&obj, format) {
+        Format::Legacy => to_legacy_recipe(obj),
+        Format::Factorio2 => to_recipe_2(obj).map(Some)
+    }
+}
+
+/// In 2.0 every component must be a table with `type`.
+fn check_component_types(obj: &LuaObject, key: &str, path: &str) -> Result<(), LuaError> {
+    if let Some(&AnObject(ref list)) = obj.get(key) {
+        for (i, &(_, ref field)) in list.fields.iter().enumerate() {
+            let component = ::deserialize::as_object(field, &format!("{}.{}[{}]", path, key, i + 1))?;
+            if component.get("type").is_none() {
+                return Err(LuaError::new(&format!("{}.{}[{}].type", path, key, i + 1), "missing field".to_string()));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn to_recipe_2(obj: LuaObject) -> Result<Recipe, LuaError> {
+    let path = prototype_path(&obj);
+    check_component_types(&obj, "ingredients", &path)?;
+    check_component_types(&obj, "results", &path)?;
+    let prototype: Recipe2Prototype = from_prototype(obj)?;
+    check_type(&prototype.a_type, "recipe", &path)?;
+    if prototype.results.is_empty() {
+        return Err(LuaError::new(&format!("{}.results", path), "recipe with no products".to_string()));
+    }
+    Ok(Recipe {
+        name: prototype.name,
+        category: prototype.category,
+        products: prototype.results,
+        ingredients: prototype.ingredients,
+        energy_required: prototype.energy_required,
+        enabled: prototype.enabled,
+        allow_productivity: prototype.allow_productivity,
+        maximum_productivity: Some(prototype.maximum_productivity),
+        surface_conditions: prototype.surface_conditions
+    })
+}
+
+fn to_legacy_recipe(obj: LuaObject) -> Result<Option<Recipe>, LuaError> {
+    let path = prototype_path(&obj);
+    if let Some(&ABool(false)) = obj.get("normal") {
+        return Ok(None);
+    }
+    let field = AnObject(Box::new(obj));
+    let prototype = RecipePrototype::from_lua(&field, &path)?;
+    check_type(&prototype.a_type, "recipe", &path)?;
+    let data = match prototype.normal {
+        Some(normal) => RecipeData::from_lua(&AnObject(Box::new(normal)), &format!("{}.normal", path))?,
+        None => RecipeData::from_lua(&field, &path)?
+    };
+    let products = match (data.results, data.result) {
+        (Some(results), _) => results,
+        (None, Some(result)) => vec![item_component(result, data.result_count)],
+        (None, None) => return Err(LuaError::new(&format!("{}.results", path), "recipe with no products".to_string()))
+    };
+    Ok(Some(Recipe {
+        name: prototype.name,
+        category: prototype.category,
+        products: products,
+        ingredients: data.ingredients,
+        energy_required: data.energy_required,
+        enabled: data.enabled,
+        allow_productivity: false,
+        maximum_productivity: None,
+        surface_conditions: Vec::new()
+    }))
+}
+
+/// Converts a technology prototype. Returns `Ok(None)` for technologies whose
+/// unit count is given by `count_formula` (infinite research).
+pub fn to_technology(obj: LuaObject) -> Result<Option<Technology>, LuaError> {
+    let path = prototype_path(&obj);
+    let prototype: TechnologyPrototype = from_prototype(obj)?;
+    check_type(&prototype.a_type, "technology", &path)?;
+    Ok(prototype.unit.count.map(|count| Technology {
+        name: prototype.name,
+        unit_count: count,
+        unit_time: prototype.unit.time,
+        unit_ingredients: prototype.unit.ingredients,
+        prerequisites: prototype.prerequisites
+    }))
+}
+
+fn component_to_lua(comp: &RecipeComponent, short: bool) -> LuaField<'static> {
+    let simple = short && comp.a_type == "item" && comp.probability == 1.0 && comp.amount_min.is_none()
+        && comp.temperature.is_none() && comp.minimum_temperature.is_none() && comp.maximum_temperature.is_none();
+    if simple {
+        let mut obj = LuaObject::new();
+        obj.push(AString(comp.name.clone().into()));
+        obj.push(AFloat(comp.amount));
+        return AnObject(Box::new(obj));
+    }
+    let mut obj = LuaObject::new()
+        .with("type", AString(comp.a_type.clone().into()))
+        .with("name", AString(comp.name.clone().into()));
+    match (comp.amount_min, comp.amount_max) {
+        (Some(min), Some(max)) => obj = obj.with("amount_min", AFloat(min)).with("amount_max", AFloat(max)),
+        _ => obj = obj.with("amount", AFloat(comp.amount))
+    }
+    let optional = [
+        ("probability", if comp.probability != 1.0 { Some(comp.probability) } else { None }),
+        ("temperature", comp.temperature),
+        ("minimum_temperature", comp.minimum_temperature),
+        ("maximum_temperature", comp.maximum_temperature)
+    ];
+    for &(key, value) in optional.iter() {
+        if let Some(value) = value {
+            obj = obj.with(key, AFloat(value));
+        }
+    }
+    AnObject(Box::new(obj))
+}
+
+/// `short` allows the legacy form `{"iron-plate", 2}` for simple items.
+fn components_to_lua(components: &[RecipeComponent], short: bool) -> LuaField<'static> {
+    let mut obj = LuaObject::new();
+    for comp in components {
+        obj.push(component_to_lua(comp, short));
+    }
+    AnObject(Box::new(obj))
+}
+
+/// Converts a recipe back to a prototype, e.g. to write it to a mod with `lua_writer`.
+/// Fields of Factorio 2.0 are only written if they are set. Recipes of 2.0
+/// (which always have `maximum_productivity`) are written without short components.
+pub fn from_recipe(recipe: &Recipe) -> LuaObject<'static> {
+    let short = recipe.maximum_productivity.is_none();
+    let mut obj = LuaObject::new()
+        .with("type", AString("recipe".into()))
+        .with("name", AString(recipe.name.clone().into()))
+        .with("category", AString(recipe.category.clone().into()))
+        .with("enabled", ABool(recipe.enabled))
+        .with("energy_required", AFloat(recipe.energy_required))
+        .with("ingredients", components_to_lua(&recipe.ingredients, short))
+        .with("results", components_to_lua(&recipe.products, short));
+    if recipe.allow_productivity {
+        obj = obj.with("allow_productivity", ABool(true));
+    }
+    if let Some(x) = recipe.maximum_productivity {
+        obj = obj.with("maximum_productivity", AFloat(x));
+    }
+    if !recipe.surface_conditions.is_empty() {
+        let mut conditions = LuaObject::new();
+        for c in &recipe.surface_conditions {
+            let mut condition = LuaObject::new().with("property", AString(c.property.clone().into()));
+            if let Some(min) = c.min {
+                condition = condition.with("min", AFloat(min));
+            }
+            if let Some(max) = c.max {
+                condition = condition.with("max", AFloat(max));
+            }
+            conditions.push(AnObject(Box::new(condition)));
+        }
+        obj = obj.with("surface_conditions", AnObject(Box::new(conditions)));
+    }
+    obj
+}
+
+#[cfg(test)]
+mod tests {
+    use lua;
+    use super::*;
+
+    fn recipe(src: &str, format: Format) -> Result<Option<Recipe>, LuaError> {
+        to_recipe(lua::parse_file(&format!("data:extend({{{}}})", src)).unwrap().remove(0).into_owned(), format)
+    }
+
+    #[test]
+    fn reads_legacy_recipes_enabled_by_default() {
+        let gear = recipe("{type = 'recipe', name = 'gear', ingredients = {{'iron-plate', 2}}, result = 'gear'}", Format::Legacy)
+            .unwrap().unwrap();
+        assert!(gear.enabled);
+        assert_eq!((gear.energy_required, &gear.category[..]), (0.5, "crafting"));
+        assert_eq!(gear.products, vec![item_component("gear".to_string(), 1.0)]);
+        let locked = recipe("{type = 'recipe', name = 'a', normal = {enabled = false, ingredients = {}, result = 'a', result_count = 2}, \
+                             expensive = {ingredients = {}, result = 'a'}}", Format::Legacy).unwrap().unwrap();
+        assert!(!locked.enabled);
+        assert_eq!(locked.products[0].amount, 2.0);
+    }
+
+    #[test]
+    fn skips_recipes_without_normal_difficulty() {
+        let src = "{type = 'recipe', name = 'a', normal = false, expensive = {ingredients = {{'b', 1}}, result = 'a'}}";
+        assert!(recipe(src, Format::Legacy).unwrap().is_none());
+        assert!(recipe(src, Format::Factorio2).unwrap().is_none());
+    }
+
+    #[test]
+    fn reads_the_legacy_layout_in_factorio_2() {
+        let gear = recipe("{type = 'recipe', name = 'gear', normal = {ingredients = {{'iron-plate', 2}}, result = 'gear'}}", Format::Factorio2)
+            .unwrap().unwrap();
+        assert_eq!((gear.ingredients[0].amount, gear.maximum_productivity), (2.0, None));
+        let src = "{type = 'recipe', name = 'gear', ingredients = {{type = 'item', name = 'iron-plate', amount = 2}}, \
+                   results = {{type = 'item', name = 'gear', amount = 1}}}";
+        assert_eq!(recipe(src, Format::Factorio2).unwrap().unwrap().maximum_productivity, Some(3.0));
+        assert_eq!(recipe(src, Format::Legacy).unwrap().unwrap().maximum_productivity, None);
+        assert_eq!(Format::of_game_version(Some("2.0.55")), Format::Factorio2);
+        assert_eq!(Format::of_game_version(None), Format::Legacy);
+    }
+
+    #[test]
+    fn reports_broken_recipes_with_their_path() {
+        let error = |src, format| recipe(src, format).err().unwrap().to_string();
+        assert_eq!(error("{type = 'recipe', ingredients = {}, result = 'a'}", Format::Legacy), "recipe.?.name: missing field");
+        assert_eq!(error("{type = 'recipe', name = 'a', ingredients = {{'b', 'two'}}, result = 'a'}", Format::Legacy),
+                   "recipe.a.ingredients[1][2]: expected number, found string \"two\"");
+        assert_eq!(error("{type = 'recipe', name = 'a', energy_required = true, ingredients = {}, \
+                          results = {{type = 'item', name = 'a', amount = 1}}}", Format::Factorio2),
+                   "recipe.a.energy_required: expected number, found boolean true");
+    }
+}
@@ -0,0 +1,320 @@
+//! Quality tiers (Factorio 2.0).
+//!
+//! A machine of better quality crafts faster, a module of better quality has
+//! stronger bonuses. Quality modules give every craft a chance to make its
+//! products one tier better than its ingredients, and then each further tier
+//! with `NEXT_TIER_CHANCE`. Products below the wanted tier can be recycled
+//! back to ingredients (again with a quality chance) and crafted again, which
+//! `quality_loop` follows until everything ends up at the target tier.
+
+use rational::Rational;
+use std::fmt;
+use std::str::FromStr;
+use structs::{Module, Recipe, TransformMachine};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Quality {
+    Normal,
+    Uncommon,
+    Rare,
+    Epic,
+    Legendary
+}
+
+use self::Quality::*;
+
+pub const QUALITIES: [Quality; 5] = [Normal, Uncommon, Rare, Epic, Legendary];
+
+/// Chance to get one more tier after a craft got a better quality.
+pub const NEXT_TIER_CHANCE: f64 = 0.1;
+
+/// Bonus per quality level to machine speed and to positive module effects.
+pub const LEVEL_BONUS: f64 = 0.3;
+
+/// Fraction of the ingredients a recycler gives back.
+pub const RECYCLING_RETURN: f64 = 0.25;
+
+impl Quality {
+    /// Level of the tier, legendary is level 5.
+    pub fn level(self) -> u32 {
+        match self {
+            Normal => 0,
+            Uncommon => 1,
+            Rare => 2,
+            Epic => 3,
+            Legendary => 5
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Normal => "normal",
+            Uncommon => "uncommon",
+            Rare => "rare",
+            Epic => "epic",
+            Legendary => "legendary"
+        }
+    }
+
+    /// Multiplier of machine speed and of positive module effects.
+    pub fn factor(self) -> Rational {
+        Rational::from_f64(LEVEL_BONUS) * self.level() + 1
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Quality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Quality, String> {
+        QUALITIES.iter().cloned().find(|x| x.name() == s).ok_or(format!("unknown quality: {}", s))
+    }
+}
+
+/// A module of some quality in a machine slot.
+#[derive(Clone, Copy)]
+pub struct ModuleSlot {
+    pub module: &'static Module,
+    pub quality: Quality
+}
+
+/// Summed module effects of a machine.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Effects {
+    pub speed: Rational,
+    pub productivity: Rational,
+    pub quality: Rational,
+    pub consumption: Rational
+}
+
+/// Sums the effects of `modules`. Module quality scales bonuses, but not
+/// penalties; speed and consumption can't go below -80%, quality below 0.
+pub fn effects(modules: &[ModuleSlot]) -> Effects {
+    let scale = |x: f64, quality: Quality| {
+        let x = Rational::from_f64(x);
+        if x > Rational::ZERO { x * quality.factor() } else { x }
+    };
+    let mut sum = Effects::default();
+    for slot in modules {
+        sum.speed += scale(slot.module.speed, slot.quality);
+        sum.productivity += scale(slot.module.productivity, slot.quality);
+        sum.quality += scale(slot.module.quality, slot.quality);
+        sum.consumption += scale(slot.module.consumption, slot.quality);
+    }
+    let floor = Rational::new(-4, 5);
+    sum.speed = sum.speed.max(floor);
+    sum.consumption = sum.consumption.max(floor);
+    sum.quality = sum.quality.max(Rational::ZERO);
+    sum
+}
+
+/// Crafting speed of a machine of `quality` with module `effects`.
+pub fn crafting_speed(machine: &TransformMachine, quality: Quality, effects: &Effects) -> Rational {
+    Rational::from_f64(machine.crafting_speed) * quality.factor() * (effects.speed + 1)
+}
+
+/// Chances of the tiers of the products of a craft with ingredients of
+/// `input` quality and the quality chance `chance`, indexed by tier.
+pub fn output_distribution(input: Quality, chance: Rational) -> [Rational; 5] {
+    let mut out = [Rational::ZERO; 5];
+    let i = input.index();
+    if input == Legendary {
+        out[i] = Rational::ONE;
+        return out;
+    }
+    let next = Rational::from_f64(NEXT_TIER_CHANCE);
+    let chance = chance.min(Rational::ONE);
+    out[i] = Rational::ONE - chance;
+    let mut p = chance;
+    for (j, x) in out.iter_mut().enumerate().skip(i + 1) {
+        if j == Legendary.index() {
+            *x = p;
+        } else {
+            *x = p * (Rational::ONE - next);
+            p *= next;
+        }
+    }
+    out
+}
+
+/// Productivity bonus of `recipe` with module effects, capped by the recipe.
+pub fn productivity(recipe: &Recipe, effects: &Effects) -> Result<Rational, String> {
+    if effects.productivity > Rational::ZERO && recipe.maximum_productivity.is_some() && !recipe.allow_productivity {
+        return Err(format!("recipe {} doesn't allow productivity", recipe.name));
+    }
+    Ok(match recipe.maximum_productivity {
+        Some(max) => effects.productivity.min(Rational::from_f64(max)),
+        None => effects.productivity
+    })
+}
+
+/// Machine setup of a quality step: module effects of the crafting machine,
+/// of the recyclers (`None` to keep products below the target) and the tier
+/// of the products that are wanted.
+pub struct QualitySetup {
+    pub crafting: Effects,
+    pub recycling: Option<Effects>,
+    pub target: Quality
+}
+
+/// Flows of a quality loop per craft's worth of normal ingredients, by tier.
+#[derive(Debug)]
+pub struct QualityYield {
+    /// Crafts, including the ones from recycled ingredients.
+    pub crafts: [Rational; 5],
+    /// All products made.
+    pub products: [Rational; 5],
+    /// Products below the target tier sent to recycling.
+    pub recycled: [Rational; 5]
+}
+
+impl QualityYield {
+    /// Products of `quality` or better.
+    pub fn at_least(&self, quality: Quality) -> Rational {
+        self.products[quality.index()..].iter().sum()
+    }
+
+    pub fn total_crafts(&self) -> Rational {
+        self.crafts.iter().sum()
+    }
+}
+
+/// Follows `recipe` crafting `product` through the quality tiers, starting
+/// with one craft's worth of normal ingredients. Products below the target
+/// are recycled and the returned ingredients crafted again (at the tier the
+/// recycler gave them) until the loop settles.
+pub fn quality_loop(recipe: &Recipe, product: &str, setup: &QualitySetup) -> Result<QualityYield, String> {
+    let amount = match recipe.products.iter().find(|x| x.name == product) {
+        Some(comp) => Rational::from_f64(comp.expected_amount()),
+        None => return Err(format!("recipe {} doesn't make {}", recipe.name, product))
+    };
+    let bonus = productivity(recipe, &setup.crafting)? + 1;
+    let recycling_return = Rational::from_f64(RECYCLING_RETURN);
+    let target = setup.target.index();
+    // transfer[i][k]: crafts of tier k from the recycled products of a craft of tier i
+    let mut transfer = [[Rational::ZERO; 5]; 5];
+    if let Some(ref recycling) = setup.recycling {
+        for i in 0..target {
+            let made = output_distribution(QUALITIES[i], setup.crafting.quality);
+            for j in i..target {
+                let returned = output_distribution(QUALITIES[j], recycling.quality);
+                for k in j..5 {
+                    transfer[i][k] += recycling_return * bonus * made[j] * returned[k];
+                }
+            }
+        }
+    }
+    let mut out = QualityYield { crafts: [Rational::ZERO; 5], products: [Rational::ZERO; 5], recycled: [Rational::ZERO; 5] };
+    for k in 0..5 {
+        let start = if k == 0 { Rational::ONE } else { Rational::ZERO };
+        let inflow = start + (0..k).map(|i| out.crafts[i] * transfer[i][k]).sum::<Rational>();
+        if transfer[k][k] >= Rational::ONE {
+            return Err(format!("recycling loop of {} grows without bound", product));
+        }
+        let crafts = inflow / (Rational::ONE - transfer[k][k]);
+        out.crafts[k] = crafts;
+        let made = output_distribution(QUALITIES[k], setup.crafting.quality);
+        for (j, p) in made.iter().enumerate().skip(k) {
+            out.products[j] += crafts * amount * bonus * *p;
+        }
+    }
+    if setup.recycling.is_some() {
+        out.recycled[..target].copy_from_slice(&out.products[..target]);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use structs::{self, RecipeComponent};
+    use super::*;
+
+    fn recipe(amount: f64, maximum_productivity: Option<f64>, allow_productivity: bool) -> Recipe {
+        let component = |name: &str, amount: f64| RecipeComponent {
+            a_type: "item".to_string(),
+            name: name.to_string(),
+            amount: amount,
+            amount_min: None,
+            amount_max: None,
+            probability: 1.0,
+            temperature: None,
+            minimum_temperature: None,
+            maximum_temperature: None
+        };
+        Recipe {
+            name: "gear".to_string(),
+            category: "crafting".to_string(),
+            products: vec![component("gear", amount)],
+            ingredients: vec![component("plate", 2.0)],
+            energy_required: 0.5,
+            enabled: true,
+            allow_productivity: allow_productivity,
+            maximum_productivity: maximum_productivity,
+            surface_conditions: Vec::new()
+        }
+    }
+
+    fn slots(module: &'static Module, quality: Quality, n: usize) -> Vec<ModuleSlot> {
+        vec![ModuleSlot { module: module, quality: quality }; n]
+    }
+
+    fn r(num: i128, den: i128) -> Rational {
+        Rational::new(num, den)
+    }
+
+    #[test]
+    fn module_quality_scales_bonuses_but_not_penalties() {
+        let normal = effects(&slots(&structs::QUALITY_MODULE_3, Normal, 4));
+        assert_eq!((normal.quality, normal.speed), (r(1, 10), r(-1, 5)));
+        let legendary = effects(&slots(&structs::QUALITY_MODULE_3, Legendary, 1));
+        assert_eq!((legendary.quality, legendary.speed), (r(1, 16), r(-1, 20)));
+        let slow = effects(&slots(&structs::PRODUCTIVITY_MODULE_3, Normal, 6));
+        assert_eq!((slow.speed, slow.productivity), (r(-4, 5), r(3, 5)));
+        assert_eq!(crafting_speed(&structs::ASSEMBLING_MACHINE_3, Rare, &normal), r(5, 4) * r(8, 5) * r(4, 5));
+    }
+
+    #[test]
+    fn distributes_products_over_tiers() {
+        assert_eq!(output_distribution(Normal, r(1, 10)), [r(9, 10), r(9, 100), r(9, 1000), r(9, 10000), r(1, 10000)]);
+        assert_eq!(output_distribution(Epic, r(3, 2)), [Rational::ZERO, Rational::ZERO, Rational::ZERO, Rational::ZERO, Rational::ONE]);
+        assert_eq!(output_distribution(Legendary, r(1, 10))[Legendary.index()], Rational::ONE);
+    }
+
+    #[test]
+    fn caps_productivity() {
+        let bonus = Effects { productivity: r(1, 2), ..Effects::default() };
+        assert_eq!(productivity(&recipe(1.0, None, false), &bonus), Ok(r(1, 2)));
+        assert_eq!(productivity(&recipe(1.0, Some(0.3), true), &bonus), Ok(r(3, 10)));
+        assert!(productivity(&recipe(1.0, Some(3.0), false), &bonus).is_err());
+    }
+
+    #[test]
+    fn follows_products_through_recycling() {
+        let crafting = Effects { quality: r(1, 10), ..Effects::default() };
+        let once = QualitySetup { crafting: crafting, recycling: None, target: Legendary };
+        let result = quality_loop(&recipe(2.0, None, false), "gear", &once).unwrap();
+        assert_eq!(result.total_crafts(), Rational::ONE);
+        assert_eq!(result.at_least(Legendary), r(2, 10000));
+        assert_eq!(result.recycled, [Rational::ZERO; 5]);
+
+        let looped = QualitySetup { crafting: crafting, recycling: Some(crafting), target: Rare };
+        let result = quality_loop(&recipe(2.0, None, false), "gear", &looped).unwrap();
+        assert!(result.total_crafts() > Rational::ONE);
+        assert!(result.at_least(Rare) > r(2, 100));
+        assert_eq!(&result.recycled[..2], &result.products[..2]);
+        assert_eq!(&result.recycled[2..], &[Rational::ZERO; 3]);
+
+        let endless = QualitySetup { crafting: Effects { productivity: r(3, 1), ..Effects::default() }, recycling: Some(Effects::default()), target: Uncommon };
+        assert!(quality_loop(&recipe(1.0, None, false), "gear", &endless).is_err());
+        assert!(quality_loop(&recipe(1.0, None, false), "plate", &once).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+//! Path queries over parsed prototypes.
+//!
+//! A path starts with the prototype type, followed by the prototype name and
+//! then by fields of the prototype, e.g. `recipe.rocket-part.ingredients[2].amount`.
+//! Segments are:
+//!
+//! * `name` - named field (prototype name right after the type),
+//! * `[n]` - n-th element of an array, indexed from 1 like in Lua,
+//! * `*` - all fields (all prototypes right after the type),
+//! * `[key=value]` or `[key!=value]` - elements (or prototypes) whose field
+//!   `key` is equal (not equal) to `value`,
+//!   e.g. `recipe[category=chemistry].name`.
+
+use std::f64;
+use std::str::FromStr;
+use lua::{LuaObject, LuaField};
+use lua::LuaField::*;
+use deserialize::{FromLua, LuaError};
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Field(String),
+    Index(usize),
+    Wildcard,
+    Filter(String, bool, String)
+}
+
+/// Value found by a query: either a whole prototype or a field inside it.
+#[derive(Debug, Clone, Copy)]
+pub enum Node<'a> {
+    Prototype(&'a LuaObject<'a>),
+    Field(&'a LuaField<'a>)
+}
+
+impl<'a> Node<'a> {
+    pub fn as_table(&self) -> Option<&'a LuaObject<'a>> {
+        match *self {
+            Node::Prototype(obj) => Some(obj),
+            Node::Field(&AnObject(ref obj)) => Some(obj),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&'a str> {
+        match *self {
+            Node::Field(&AString(ref s)) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Node::Field(&AFloat(x)) => Some(x),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Node::Field(&ABool(x)) => Some(x),
+            _ => None
+        }
+    }
+
+    /// Deserializes the value into a typed struct, see `deserialize`.
+    pub fn deserialize<T: FromLua>(&self, path: &str) -> Result<T, LuaError> {
+        match *self {
+            Node::Prototype(obj) => T::from_lua(&AnObject(Box::new(obj.clone())), path),
+            Node::Field(field) => T::from_lua(field, path)
+        }
+    }
+}
+
+/// Single query result with the exact path to it.
+#[derive(Debug)]
+pub struct QueryResult<'a> {
+    pub path: String,
+    pub node: Node<'a>
+}
+
+fn parse_path(path: &str) -> Result<Vec<Segment>, LuaError> {
+    let error = |message: &str| LuaError::new(path, message.to_string());
+    let mut segments = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        if rest.starts_with(".") {
+            rest = &rest[1..];
+            if rest.is_empty() || rest.starts_with(".") || rest.starts_with("[") {
+                return Err(error("empty segment"));
+            }
+        }
+        if rest.starts_with("[") {
+            let end = match rest.find("]") {
+                Some(end) => end,
+                None => return Err(error("unclosed \"[\""))
+            };
+            let inner = &rest[1..end];
+            rest = &rest[end + 1..];
+            if let Some(i) = inner.find("=") {
+                let negated = inner[..i].ends_with("!");
+                let key = if negated { &inner[..i - 1] } else { &inner[..i] };
+                segments.push(Segment::Filter(key.trim().to_string(), negated, inner[i + 1..].trim().to_string()));
+            } else {
+                match usize::from_str(inner.trim()) {
+                    Ok(n) if n > 0 => segments.push(Segment::Index(n)),
+                    _ => return Err(error("array index must be a positive number"))
+                }
+            }
+        } else {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            let name = &rest[..end];
+            segments.push(if name == "*" { Segment::Wildcard } else { Segment::Field(name.to_string()) });
+            rest = &rest[end..];
+        }
+    }
+    if segments.is_empty() {
+        return Err(error("empty path"));
+    }
+    Ok(segments)
+}
+
+fn matches(obj: &LuaObject, key: &str, negated: bool, value: &str) -> bool {
+    let equal = match obj.get(key) {
+        Some(&AString(ref s)) => s == value,
+        Some(&AFloat(x)) => f64::from_str(value).map(|v| v == x).unwrap_or(false),
+        Some(&ABool(x)) => bool::from_str(value).map(|v| v == x).unwrap_or(false),
+        Some(&AnObject(_)) | None => false
+    };
+    equal != negated
+}
+
+fn name_of<'a>(obj: &'a LuaObject) -> &'a str {
+    match obj.get("name") {
+        Some(&AString(ref s)) => s,
+        _ => "?"
+    }
+}
+
+fn name_of_type<'a>(obj: &'a LuaObject) -> &'a str {
+    match obj.get("type") {
+        Some(&AString(ref s)) => s,
+        _ => "?"
+    }
+}
+
+/// Runs the query `path` over `prototypes` and returns all matching values.
+pub fn query<'a>(prototypes: &'a [LuaObject<'a>], path: &str) -> Result<Vec<QueryResult<'a>>, LuaError> {
+    let segments = parse_path(path)?;
+    let mut iter = segments.into_iter();
+    let mut results: Vec<QueryResult<'a>> = match iter.next() {
+        Some(Segment::Field(a_type)) => prototypes.iter()
+            .filter(|obj| matches(obj, "type", false, &a_type))
+            .map(|obj| QueryResult { path: format!("{}.{}", a_type, name_of(obj)), node: Node::Prototype(obj) })
+            .collect(),
+        Some(Segment::Wildcard) => prototypes.iter()
+            .map(|obj| QueryResult { path: format!("{}.{}", name_of_type(obj), name_of(obj)), node: Node::Prototype(obj) })
+            .collect(),
+        _ => return Err(LuaError::new(path, "path must start with a prototype type".to_string()))
+    };
+    // The segment right after the type selects prototypes by name.
+    let mut selecting_prototypes = true;
+    for segment in iter {
+        if selecting_prototypes {
+            selecting_prototypes = false;
+            results = match segment {
+                Segment::Field(name) => results.into_iter().filter(|r| r.node.as_table().is_some_and(|obj| name_of(obj) == name)).collect(),
+                Segment::Wildcard => results,
+                Segment::Index(n) => results.into_iter().skip(n - 1).take(1).collect(),
+                Segment::Filter(key, negated, value) => results.into_iter()
+                    .filter(|r| r.node.as_table().is_some_and(|obj| matches(obj, &key, negated, &value)))
+                    .collect()
+            };
+            continue;
+        }
+        let mut next = Vec::new();
+        for result in results {
+            let obj = match result.node.as_table() {
+                Some(obj) => obj,
+                None => continue
+            };
+            match segment {
+                Segment::Field(ref name) => {
+                    if let Some(field) = obj.get(name) {
+                        next.push(QueryResult { path: format!("{}.{}", result.path, name), node: Node::Field(field) });
+                    }
+                },
+                Segment::Index(n) => {
+                    if let Some(&(_, ref field)) = obj.fields.get(n - 1) {
+                        next.push(QueryResult { path: format!("{}[{}]", result.path, n), node: Node::Field(field) });
+                    }
+                },
+                Segment::Wildcard => {
+                    for (i, &(ref name, ref field)) in obj.fields.iter().enumerate() {
+                        let path = match *name {
+                            Some(ref name) => format!("{}.{}", result.path, name),
+                            None => format!("{}[{}]", result.path, i + 1)
+                        };
+                        next.push(QueryResult { path: path, node: Node::Field(field) });
+                    }
+                },
+                Segment::Filter(ref key, negated, ref value) => {
+                    for (i, &(_, ref field)) in obj.fields.iter().enumerate() {
+                        if let AnObject(ref element) = *field {
+                            if matches(element, key, negated, value) {
+                                next.push(QueryResult { path: format!("{}[{}]", result.path, i + 1), node: Node::Field(field) });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        results = next;
+    }
+    Ok(results)
+}
+
+/// Runs the query and deserializes a single result, e.g.
+/// `query_one::<f64>(&prototypes, "recipe.rocket-part.energy_required")`.
+pub fn query_one<T: FromLua>(prototypes: &[LuaObject], path: &str) -> Result<T, LuaError> {
+    let results = query(prototypes, path)?;
+    match results.len() {
+        1 => results[0].node.deserialize(&results[0].path),
+        n => Err(LuaError::new(path, format!("expected exactly one result, found {}", n)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lua::parse_file;
+    use super::*;
+
+    const PROTOTYPES: &'static str = r#"data:extend({
+        {type = "recipe", name = "rocket-part", category = "rocket-building", energy_required = 3,
+         ingredients = {{type = "item", name = "low-density-structure", amount = 10}, {type = "fluid", name = "water", amount = 5}}},
+        {type = "recipe", name = "plastic-bar", category = "chemistry", enabled = false, energy_required = 1},
+        {type = "item", name = "wood", stack_size = 100}
+    })"#;
+
+    fn paths(prototypes: &[LuaObject], path: &str) -> Vec<String> {
+        query(prototypes, path).unwrap().into_iter().map(|x| x.path).collect()
+    }
+
+    #[test]
+    fn selects_prototypes_and_fields() {
+        let prototypes = parse_file(PROTOTYPES).unwrap();
+        assert_eq!(paths(&prototypes, "recipe"), vec!["recipe.rocket-part", "recipe.plastic-bar"]);
+        assert_eq!(paths(&prototypes, "*[type=item]"), vec!["item.wood"]);
+        assert_eq!(paths(&prototypes, "recipe[category!=chemistry].name"), vec!["recipe.rocket-part.name"]);
+        assert_eq!(paths(&prototypes, "recipe[enabled=false]"), vec!["recipe.plastic-bar"]);
+        assert_eq!(paths(&prototypes, "recipe[2].energy_required"), vec!["recipe.plastic-bar.energy_required"]);
+        assert_eq!(paths(&prototypes, "recipe.rocket-part.ingredients[type=fluid].name"), vec!["recipe.rocket-part.ingredients[2].name"]);
+        assert_eq!(paths(&prototypes, "recipe.rocket-part.ingredients[1].*").len(), 3);
+        assert!(paths(&prototypes, "recipe.missing").is_empty());
+    }
+
+    #[test]
+    fn deserializes_single_results() {
+        let prototypes = parse_file(PROTOTYPES).unwrap();
+        assert_eq!(query_one::<f64>(&prototypes, "recipe.rocket-part.ingredients[1].amount").unwrap(), 10.0);
+        assert_eq!(query_one::<String>(&prototypes, "*.wood.type").unwrap(), "item");
+        assert!(query_one::<f64>(&prototypes, "recipe.*.energy_required").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        for path in &["", "recipe..name", "recipe[0]", "recipe[x", "[1]"] {
+            assert!(query(&[], path).is_err(), "{:?}", path);
+        }
+    }
+}
@@ -0,0 +1,434 @@
+//! Exact rational numbers for rates, times and machine counts, so that
+//! ratios like 3 cables per 2 circuits come out exact instead of
+//! `4.6666665`.
+//!
+//! Values from data files are decimals (`3.2`, `0.5`) and are read exactly.
+//! Arithmetic is exact as long as numerator and denominator fit in 128 bits;
+//! beyond that the result is the closest fraction with a denominator up to
+//! `MAX_APPROXIMATE_DENOMINATOR`.
+//!
+//! Numbers are shown as decimals, rounded to `DECIMAL_PLACES` if they don't
+//! end, or as fractions (`14/3`) after `set_fractions(true)`.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+
+/// Largest denominator of the fraction used when a value is not exact.
+pub const MAX_APPROXIMATE_DENOMINATOR: i128 = 1_000_000_000_000;
+
+/// Decimal places of numbers that aren't finite decimals.
+pub const DECIMAL_PLACES: u32 = 6;
+
+static FRACTIONS: AtomicBool = AtomicBool::new(false);
+
+/// Shows numbers as fractions instead of decimals.
+pub fn set_fractions(fractions: bool) {
+    FRACTIONS.store(fractions, AtomicOrdering::Relaxed);
+}
+
+/// A fraction in lowest terms with a positive denominator.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    num: i128,
+    den: i128
+}
+
+pub fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a.abs()
+}
+
+impl Rational {
+    pub const ZERO: Rational = Rational { num: 0, den: 1 };
+    pub const ONE: Rational = Rational { num: 1, den: 1 };
+
+    /// `num / den`, panics if `den` is 0.
+    pub fn new(num: i128, den: i128) -> Rational {
+        if den == 0 {
+            panic!("rational with zero denominator: {}/0", num);
+        }
+        let g = gcd(num, den);
+        let sign = if den < 0 { -1 } else { 1 };
+        Rational { num: sign * num / g, den: sign * den / g }
+    }
+
+    pub fn integer(n: i64) -> Rational {
+        Rational { num: n as i128, den: 1 }
+    }
+
+    pub fn numer(self) -> i128 {
+        self.num
+    }
+
+    pub fn denom(self) -> i128 {
+        self.den
+    }
+
+    /// The value of `x`: exact for decimals as they are written in data
+    /// files, approximate for others (e.g. `1.0 / 3.0`).
+    pub fn from_f64(x: f64) -> Rational {
+        if !x.is_finite() {
+            panic!("not a finite number: {}", x);
+        }
+        Rational::from_str(&x.to_string()).unwrap_or_else(|_| Rational::approximate(x))
+    }
+
+    pub fn from_f32(x: f32) -> Rational {
+        if !x.is_finite() {
+            panic!("not a finite number: {}", x);
+        }
+        Rational::from_str(&x.to_string()).unwrap_or_else(|_| Rational::approximate(x as f64))
+    }
+
+    /// The closest fraction to `x` with a denominator up to
+    /// `MAX_APPROXIMATE_DENOMINATOR`, found by continued fractions.
+    pub fn approximate(x: f64) -> Rational {
+        if !x.is_finite() || x.abs() >= 1e20 {
+            panic!("number out of range: {}", x);
+        }
+        let (mut p0, mut q0, mut p1, mut q1) = (0i128, 1i128, 1i128, 0i128);
+        let mut r = x.abs();
+        loop {
+            let a = r.floor() as i128;
+            let (p2, q2) = (a * p1 + p0, a * q1 + q0);
+            if q2 > MAX_APPROXIMATE_DENOMINATOR {
+                break;
+            }
+            p0 = p1;
+            q0 = q1;
+            p1 = p2;
+            q1 = q2;
+            let frac = r - a as f64;
+            if frac < 1e-12 {
+                break;
+            }
+            r = 1.0 / frac;
+        }
+        Rational::new(if x < 0.0 { -p1 } else { p1 }, q1.max(1))
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.to_f64() as f32
+    }
+
+    pub fn is_integer(self) -> bool {
+        self.den == 1
+    }
+
+    pub fn floor(self) -> Rational {
+        Rational::new(self.num.div_euclid(self.den), 1)
+    }
+
+    pub fn ceil(self) -> Rational {
+        -(-self).floor()
+    }
+
+    /// Nearest integer, halves round up.
+    pub fn round(self) -> Rational {
+        (self + Rational::new(1, 2)).floor()
+    }
+
+    pub fn abs(self) -> Rational {
+        Rational { num: self.num.abs(), den: self.den }
+    }
+
+    /// `1 / self`, panics if `self` is 0.
+    pub fn recip(self) -> Rational {
+        Rational::new(self.den, self.num)
+    }
+
+    /// Result of an operation, or the closest fraction to `approx` if
+    /// numerator or denominator overflowed.
+    fn checked(num: Option<i128>, den: Option<i128>, approx: f64) -> Rational {
+        match (num, den) {
+            (Some(num), Some(den)) => Rational::new(num, den),
+            _ => Rational::approximate(approx)
+        }
+    }
+
+    /// Shows the number as a decimal, rounded if it doesn't end.
+    fn fmt_decimal(self, f: &mut fmt::Formatter) -> fmt::Result {
+        let abs = self.abs();
+        let mut whole = abs.floor().num;
+        let frac = abs - Rational::new(whole, 1);
+        // A finite decimal has a power of ten as a multiple of its denominator.
+        let exact = (0..=DECIMAL_PLACES + 12).find(|&p| 10i128.pow(p) % frac.den == 0)
+            .and_then(|p| frac.num.checked_mul(10i128.pow(p) / frac.den).map(|digits| (digits, p)));
+        let (mut digits, mut places) = match exact {
+            Some(x) => x,
+            None => ((frac * Rational::integer(10i64.pow(DECIMAL_PLACES))).round().num, DECIMAL_PLACES)
+        };
+        if digits == 10i128.pow(places) {
+            whole += 1;
+            digits = 0;
+        }
+        while places > 0 && digits % 10 == 0 {
+            digits /= 10;
+            places -= 1;
+        }
+        let sign = if self.num < 0 && (whole != 0 || digits != 0) { "-" } else { "" };
+        if places == 0 {
+            return write!(f, "{}{}", sign, whole);
+        }
+        write!(f, "{}{}.{:0width$}", sign, whole, digits, width = places as usize)
+    }
+}
+
+impl Default for Rational {
+    fn default() -> Rational {
+        Rational::ZERO
+    }
+}
+
+impl From<i32> for Rational {
+    fn from(n: i32) -> Rational {
+        Rational::integer(n as i64)
+    }
+}
+
+impl From<u32> for Rational {
+    fn from(n: u32) -> Rational {
+        Rational::integer(n as i64)
+    }
+}
+
+impl<T: Into<Rational>> Add<T> for Rational {
+    type Output = Rational;
+
+    fn add(self, other: T) -> Rational {
+        let other = other.into();
+        let g = gcd(self.den, other.den);
+        let num = self.num.checked_mul(other.den / g)
+            .and_then(|a| other.num.checked_mul(self.den / g).and_then(|b| a.checked_add(b)));
+        Rational::checked(num, (self.den / g).checked_mul(other.den), self.to_f64() + other.to_f64())
+    }
+}
+
+impl<T: Into<Rational>> Sub<T> for Rational {
+    type Output = Rational;
+
+    fn sub(self, other: T) -> Rational {
+        self + -other.into()
+    }
+}
+
+impl<T: Into<Rational>> Mul<T> for Rational {
+    type Output = Rational;
+
+    fn mul(self, other: T) -> Rational {
+        let other = other.into();
+        if self.num == 0 || other.num == 0 {
+            return Rational::ZERO;
+        }
+        let g1 = gcd(self.num, other.den);
+        let g2 = gcd(other.num, self.den);
+        let num = (self.num / g1).checked_mul(other.num / g2);
+        let den = (self.den / g2).checked_mul(other.den / g1);
+        Rational::checked(num, den, self.to_f64() * other.to_f64())
+    }
+}
+
+impl<T: Into<Rational>> Div<T> for Rational {
+    type Output = Rational;
+
+    // Dividing is multiplying with the reciprocal.
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, other: T) -> Rational {
+        self * other.into().recip()
+    }
+}
+
+impl Neg for Rational {
+    type Output = Rational;
+
+    fn neg(self) -> Rational {
+        Rational { num: -self.num, den: self.den }
+    }
+}
+
+impl<T: Into<Rational>> AddAssign<T> for Rational {
+    fn add_assign(&mut self, other: T) {
+        *self = *self + other;
+    }
+}
+
+impl<T: Into<Rational>> SubAssign<T> for Rational {
+    fn sub_assign(&mut self, other: T) {
+        *self = *self - other;
+    }
+}
+
+impl<T: Into<Rational>> MulAssign<T> for Rational {
+    fn mul_assign(&mut self, other: T) {
+        *self = *self * other;
+    }
+}
+
+impl<T: Into<Rational>> DivAssign<T> for Rational {
+    fn div_assign(&mut self, other: T) {
+        *self = *self / other;
+    }
+}
+
+impl Sum for Rational {
+    fn sum<I: Iterator<Item = Rational>>(iter: I) -> Rational {
+        iter.fold(Rational::ZERO, |a, b| a + b)
+    }
+}
+
+impl<'a> Sum<&'a Rational> for Rational {
+    fn sum<I: Iterator<Item = &'a Rational>>(iter: I) -> Rational {
+        iter.fold(Rational::ZERO, |a, b| a + *b)
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Rational) -> Ordering {
+        match (self.num.checked_mul(other.den), other.num.checked_mul(self.den)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => self.to_f64().partial_cmp(&other.to_f64()).unwrap_or(Ordering::Equal)
+        }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Rational) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if FRACTIONS.load(AtomicOrdering::Relaxed) && self.den != 1 {
+            write!(f, "{}/{}", self.num, self.den)
+        } else {
+            self.fmt_decimal(f)
+        }
+    }
+}
+
+impl fmt::Debug for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.num, self.den)
+    }
+}
+
+/// Parses integers (`7`), decimals (`3.2`, `1e-3`) and fractions (`14/3`).
+impl FromStr for Rational {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Rational, String> {
+        let s = s.trim();
+        let error = || format!("not a number: {}", s);
+        if let Some(i) = s.find('/') {
+            let num = Rational::from_str(&s[..i])?;
+            let den = Rational::from_str(&s[i + 1..])?;
+            if den == Rational::ZERO {
+                return Err(error());
+            }
+            return Ok(num / den);
+        }
+        let (mantissa, exponent) = match s.find(['e', 'E']) {
+            Some(i) => (&s[..i], i32::from_str(&s[i + 1..]).map_err(|_| error())?),
+            None => (s, 0)
+        };
+        let (int, frac) = match mantissa.find('.') {
+            Some(i) => (&mantissa[..i], &mantissa[i + 1..]),
+            None => (mantissa, "")
+        };
+        let (negative, int) = match int.strip_prefix('-') {
+            Some(int) => (true, int),
+            None => (false, int.strip_prefix('+').unwrap_or(int))
+        };
+        if (int.is_empty() && frac.is_empty()) || !int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(error());
+        }
+        let digits = format!("{}{}", int, frac).trim_start_matches('0').to_string();
+        let num = if digits.is_empty() { 0 } else { i128::from_str(&digits).map_err(|_| error())? };
+        let exponent = exponent - frac.len() as i32;
+        let power = 10i128.checked_pow(exponent.unsigned_abs()).ok_or_else(error)?;
+        let value = if exponent >= 0 {
+            Rational::new(num.checked_mul(power).ok_or_else(error)?, 1)
+        } else {
+            Rational::new(num, power)
+        };
+        Ok(if negative { -value } else { value })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_reduces_and_moves_the_sign_to_the_numerator() {
+        let x = Rational::new(6, -4);
+        assert_eq!((x.numer(), x.denom()), (-3, 2));
+        assert_eq!(Rational::new(0, -5), Rational::ZERO);
+        assert_eq!(Rational::new(-7, -7), Rational::ONE);
+    }
+
+    #[test]
+    fn overflow_falls_back_to_the_closest_fraction() {
+        let e = 10i128.pow(20);
+        let x = Rational::new(e + 1, e);
+        let y = Rational::new(e + 3, e + 7);
+        let close = |a: Rational, b: Rational| (a - b).abs() < Rational::new(1, 1_000_000_000);
+        assert!(close(x + y, Rational::from(2)));
+        assert!(close(x * y, Rational::ONE));
+    }
+
+    #[test]
+    fn decimals_are_rounded_when_they_dont_end() {
+        assert_eq!(Rational::new(1, 3).to_string(), "0.333333");
+        assert_eq!(Rational::new(-2, 3).to_string(), "-0.666667");
+        assert_eq!(Rational::new(1, 8).to_string(), "0.125");
+        assert_eq!(Rational::new(-7, 2).to_string(), "-3.5");
+        assert_eq!(Rational::new(2999999999, 3000000000).to_string(), "1");
+        assert_eq!(Rational::new(-1, 3000000000).to_string(), "0");
+    }
+
+    #[test]
+    fn huge_numbers_are_shown_without_overflow() {
+        let x = Rational::new(i128::MAX, 2);
+        assert_eq!(x.to_string(), format!("{}.5", i128::MAX / 2));
+    }
+
+    #[test]
+    fn from_f64_reads_decimals_exactly() {
+        assert_eq!(Rational::from_f64(0.1), Rational::new(1, 10));
+        assert_eq!(Rational::from_f64(-2.5), Rational::new(-5, 2));
+        assert_eq!(Rational::from_f32(3.2), Rational::new(16, 5));
+    }
+
+    #[test]
+    fn parses_numbers() {
+        let parse = |s: &str| Rational::from_str(s).unwrap();
+        assert_eq!(parse("7"), Rational::from(7));
+        assert_eq!(parse("+5"), Rational::from(5));
+        assert_eq!(parse("-3.5"), Rational::new(-7, 2));
+        assert_eq!(parse("1e-3"), Rational::new(1, 1000));
+        assert_eq!(parse(".5"), Rational::new(1, 2));
+        assert_eq!(parse("14/3"), Rational::new(14, 3));
+        assert_eq!(parse("-1/-2"), Rational::new(1, 2));
+    }
+
+    #[test]
+    fn rejects_malformed_numbers() {
+        for s in &["", "-", ".", "--5", "+-5", "-+-3", "1.2.3", "abc", "1/0", "1e", "5-"] {
+            assert!(Rational::from_str(s).is_err(), "{:?}", s);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Recycling (Factorio 2.0): the recycler reverses the recipe of an item and
+//! gives back a quarter of its ingredients. Recycling recipes are not in the
+//! prototype files, the game generates them, so they are generated here too.
+
+use std::collections::HashMap;
+use quality::RECYCLING_RETURN;
+use solver::{build_dependency_net, byproduct_rates, make_plan, recipes_by_product, AssemblePlan, ComponentType, ProductRecipe};
+use rational::Rational;
+use structs::{Recipe, RecipeComponent, RECYCLER};
+
+/// Recycling takes this fraction of the time of the reversed recipe.
+pub const TIME_FACTOR: f64 = 1.0 / 16.0;
+
+/// Categories the game doesn't reverse; their products recycle into themselves.
+pub const NOT_REVERSIBLE: [&'static str; 4] = ["smelting", "chemistry", "oil-processing", "recycling"];
+
+pub fn recycling_name(item: &str) -> String {
+    format!("{}-recycling", item)
+}
+
+fn item(name: &str, amount: f64) -> RecipeComponent {
+    RecipeComponent {
+        a_type: "item".to_string(),
+        name: name.to_string(),
+        amount: amount,
+        amount_min: None,
+        amount_max: None,
+        probability: 1.0,
+        temperature: None,
+        minimum_temperature: None,
+        maximum_temperature: None
+    }
+}
+
+/// Whether the recycler gives back the ingredients of `recipe`: it must make
+/// a single item, and use some items that aren't fluids.
+fn is_reversible(recipe: &Recipe) -> bool {
+    recipe.products.len() == 1 && recipe.products[0].a_type == "item"
+        && recipe.ingredients.iter().any(|x| x.a_type == "item")
+        && !NOT_REVERSIBLE.contains(&&recipe.category[..])
+}
+
+/// Recycling recipe of one `item` made by `recipe`. Fluid ingredients are
+/// lost; amounts are expected values, e.g. 0.5 for 2 plates of a gear.
+/// Items without a reversible recipe give back a quarter of themselves.
+pub fn recycling_recipe(name: &str, recipe: Option<&Recipe>) -> Recipe {
+    let (products, energy_required) = match recipe {
+        Some(recipe) if is_reversible(recipe) => {
+            let amount = recipe.products[0].expected_amount();
+            let products = recipe.ingredients.iter()
+                .filter(|x| x.a_type == "item")
+                .map(|x| item(&x.name, x.amount * RECYCLING_RETURN / amount))
+                .collect();
+            (products, recipe.energy_required * TIME_FACTOR)
+        },
+        Some(recipe) => (vec![item(name, RECYCLING_RETURN)], recipe.energy_required * TIME_FACTOR),
+        None => (vec![item(name, RECYCLING_RETURN)], 0.5 * TIME_FACTOR)
+    };
+    Recipe {
+        name: recycling_name(name),
+        category: "recycling".to_string(),
+        products: products,
+        ingredients: vec![item(name, 1.0)],
+        energy_required: energy_required,
+        enabled: true,
+        allow_productivity: false,
+        maximum_productivity: Some(0.0),
+        surface_conditions: Vec::new()
+    }
+}
+
+/// Recycling recipes of every item made by `recipes`, keyed on the item. The
+/// recipe named after an item is reversed, otherwise the first one making it.
+pub fn recycling_recipes(recipes: &[Recipe]) -> HashMap<String, Recipe> {
+    recipes_by_product(recipes).into_iter()
+        .filter(|&(name, r)| r.products.iter().any(|x| x.name == name && x.a_type == "item"))
+        .map(|(name, r)| (name.to_string(), recycling_recipe(name, Some(r))))
+        .collect()
+}
+
+/// Recyclers for a flow of one item and what they give back.
+#[derive(Debug)]
+pub struct Recycling {
+    pub item: String,
+    pub rate: Rational,
+    pub recyclers: Rational,
+    pub returns: Vec<(String, Rational)>
+}
+
+/// Recycles `rate` items/s of `item` with normal recyclers without modules.
+pub fn recycle(recipes: &HashMap<String, Recipe>, item: &str, rate: Rational) -> Recycling {
+    let recipe = match recipes.get(item) {
+        Some(recipe) => recipe.clone(),
+        None => recycling_recipe(item, None)
+    };
+    Recycling {
+        item: item.to_string(),
+        rate: rate,
+        recyclers: rate * Rational::from_f64(recipe.energy_required) / Rational::from_f64(RECYCLER.crafting_speed),
+        returns: recipe.products.iter().map(|x| (x.name.clone(), rate * Rational::from_f64(x.expected_amount()))).collect()
+    }
+}
+
+pub const MAX_ROUNDS: u32 = 100;
+
+/// Plan with surplus items and byproducts sent to recyclers. What the
+/// recyclers give back is used by the plan, lowering its demand, the rest
+/// is left as surplus.
+#[derive(Debug)]
+pub struct RecyclingPlan {
+    pub plan: AssemblePlan,
+    pub recycling: Vec<Recycling>,
+    pub surplus: Vec<(String, Rational)>
+}
+
+/// Plans `targets`, recycling `surplus` (items/s) and, with `byproducts`,
+/// the item byproducts of the plan. Returned items lower the demand, which
+/// lowers the byproducts in turn, so this repeats until the flows settle
+/// (or for `MAX_ROUNDS`).
+pub fn plan_with_recycling(dependencies: &HashMap<String, ProductRecipe>, recipes: &HashMap<String, Recipe>,
+                           targets: &HashMap<String, Rational>, surplus: &[(String, Rational)], byproducts: bool) -> RecyclingPlan {
+    let mut credits: HashMap<String, Rational> = HashMap::new();
+    let mut round = 0;
+    loop {
+        round += 1;
+        let mut adjusted = targets.clone();
+        for (name, credit) in &credits {
+            *adjusted.entry(name.clone()).or_insert(Rational::ZERO) -= *credit;
+        }
+        let graph = build_dependency_net(dependencies, targets.keys().cloned().collect());
+        let plan = make_plan(dependencies, &adjusted, graph);
+        let mut inputs: Vec<(String, Rational)> = surplus.to_vec();
+        if byproducts {
+            inputs.extend(byproduct_rates(dependencies, &plan).into_iter()
+                .filter(|x| x.2 == ComponentType::Item && x.1 > Rational::ZERO)
+                .map(|x| (x.0, x.1)));
+        }
+        let recycling: Vec<_> = inputs.iter().map(|&(ref item, rate)| recycle(recipes, item, rate)).collect();
+        let mut returns: HashMap<String, Rational> = HashMap::new();
+        for r in &recycling {
+            for &(ref name, rate) in &r.returns {
+                *returns.entry(name.clone()).or_insert(Rational::ZERO) += rate;
+            }
+        }
+        let mut next = HashMap::new();
+        for (name, &rate) in &returns {
+            if let Some(param) = plan.compounds.get(name) {
+                let demand = param.rate + credits.get(name).cloned().unwrap_or_default();
+                next.insert(name.clone(), rate.min(demand));
+            }
+        }
+        let settled = next.len() == credits.len()
+            && next.iter().all(|(name, &x)| credits.get(name).is_some_and(|&y| (x - y).abs() * 100_000 <= x.max(Rational::ONE)));
+        if settled || round == MAX_ROUNDS {
+            let mut left: Vec<_> = returns.into_iter()
+                .map(|(name, rate)| { let used = next.get(&name).cloned().unwrap_or_default(); (name, rate - used) })
+                .filter(|x| x.1 > Rational::ZERO)
+                .collect();
+            left.sort_by(|a, b| a.0.cmp(&b.0));
+            return RecyclingPlan { plan: plan, recycling: recycling, surplus: left };
+        }
+        credits = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use data_file;
+    use solver::to_product_recipes;
+    use super::*;
+
+    /// Casting makes a rod with every gear; rods are pressed from plates.
+    const RECIPES: &'static str = "format\t8\n\
+        recipe\tcast\nenergy_required\t1\ningredient\titem\tplate\t4\nproduct\titem\tgear\t1\nproduct\titem\trod\t1\nend\n\
+        recipe\trod\nenergy_required\t1\ningredient\titem\tplate\t1\nproduct\titem\trod\t2\nend\n";
+
+    fn plan(recipes: &str, surplus: &[(String, Rational)]) -> RecyclingPlan {
+        let recipes = data_file::read_recipes(recipes).unwrap();
+        let targets = vec![("gear".to_string(), Rational::ONE)].into_iter().collect();
+        plan_with_recycling(&to_product_recipes(&recipes), &recycling_recipes(&recipes), &targets, surplus, true)
+    }
+
+    fn r(num: i128, den: i128) -> Rational {
+        Rational::new(num, den)
+    }
+
+    #[test]
+    fn recycles_unneeded_byproducts_and_caps_returns_at_demand() {
+        let result = plan(RECIPES, &[]);
+        // a rod gives back 1/8 plate, the plan needs 4 plates per gear
+        let rods = result.recycling.iter().find(|x| x.item == "rod").unwrap();
+        assert_eq!((rods.rate, rods.returns.clone()), (Rational::ONE, vec![("plate".to_string(), r(1, 8))]));
+        assert_eq!(result.plan.compounds["plate"].rate, r(31, 8));
+        assert!(result.surplus.is_empty());
+
+        // cast gears recycle into a quarter of themselves, more than the plan needs
+        let result = plan(RECIPES, &[("gear".to_string(), Rational::from(8))]);
+        assert_eq!(result.surplus, vec![("gear".to_string(), Rational::ONE)]);
+        assert_eq!(result.plan.compounds["plate"].rate, Rational::ZERO);
+    }
+
+    #[test]
+    fn repeats_until_returns_settle() {
+        // rods are bent from gears now, so recycling them gives back gears,
+        // which lowers the casting and with it the rods: 1/9 of the gears
+        let recipes = RECIPES.replace("recipe\trod\nenergy_required\t1\ningredient\titem\tplate", "recipe\trod\nenergy_required\t1\ningredient\titem\tgear");
+        let result = plan(&recipes, &[]);
+        let rods = result.recycling.iter().find(|x| x.item == "rod").unwrap();
+        assert_eq!(rods.returns, vec![("gear".to_string(), rods.rate / 8)]);
+        assert!((rods.rate - r(8, 9)).abs() * 10_000 < Rational::ONE);
+        assert!((result.plan.compounds["plate"].rate - r(32, 9)).abs() * 10_000 < Rational::ONE);
+        assert!(result.surplus.is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+//! Research planning: science pack rates and labs for a science-per-minute
+//! target or for a queue of technologies from the technology blocks of the
+//! data file.
+
+use std::collections::{HashMap, HashSet};
+use rational::Rational;
+use structs::{self, Entity};
+
+#[derive(Debug)]
+pub struct Technology {
+    pub name: String,
+    pub count: Rational,
+    pub time: Rational,
+    pub ingredients: Vec<(Rational, String)>,
+    pub prerequisites: Vec<String>
+}
+
+#[derive(Debug)]
+pub struct ResearchPlan {
+    pub pack_rates: Vec<(String, Rational)>,
+    pub labs: Rational
+}
+
+/// Technologies of a data file by name, with exact unit counts and times.
+pub fn technologies(data: &[structs::Technology]) -> HashMap<String, Technology> {
+    data.iter().map(|x| (x.name.clone(), Technology {
+        name: x.name.clone(),
+        count: Rational::from_f64(x.unit_count),
+        time: Rational::from_f64(x.unit_time),
+        ingredients: x.unit_ingredients.iter().map(|c| (Rational::from_f64(c.amount), c.name.clone())).collect(),
+        prerequisites: x.prerequisites.clone()
+    })).collect()
+}
+
+/// Research speed of the lab `name`: from its machine block in the data file,
+/// else the built-in one for the vanilla lab.
+pub fn lab_speed(machines: &[Entity], name: &str) -> Result<Rational, String> {
+    match machines.iter().find(|x| x.name == name).map(|x| x.researching_speed) {
+        Some(Some(speed)) => Ok(Rational::from_f64(speed)),
+        Some(None) => Err(format!("{} isn't a lab", name)),
+        None if name == structs::LAB.name => Ok(Rational::from_f64(structs::LAB.researching_speed)),
+        None => Err(format!("unknown lab: {}", name))
+    }
+}
+
+/// Adds all (transitive) prerequisites to the queue, keeping every technology
+/// after its prerequisites. Technologies from `researched` are left out.
+pub fn expand_queue(technologies: &HashMap<String, Technology>, queue: &[String], researched: &HashSet<String>) -> Result<Vec<String>, String> {
+    fn visit(technologies: &HashMap<String, Technology>, name: &str, done: &mut HashSet<String>, out: &mut Vec<String>) -> Result<(), String> {
+        if done.contains(name) {
+            return Ok(());
+        }
+        done.insert(name.to_string());
+        let technology = find(technologies, name)?;
+        for prerequisite in &technology.prerequisites {
+            visit(technologies, prerequisite, done, out)?;
+        }
+        out.push(name.to_string());
+        Ok(())
+    }
+
+    let mut done = researched.clone();
+    let mut out = Vec::new();
+    for name in queue {
+        visit(technologies, name, &mut done, &mut out)?;
+    }
+    Ok(out)
+}
+
+fn find<'t>(technologies: &'t HashMap<String, Technology>, name: &str) -> Result<&'t Technology, String> {
+    technologies.get(name).ok_or_else(|| format!("unknown technology: {}", name))
+}
+
+/// Labs and science pack rates needed to finish the whole queue in `seconds`.
+pub fn plan_queue(technologies: &HashMap<String, Technology>, queue: &[String], seconds: Rational, lab_speed: Rational, research_bonus: Rational) -> Result<ResearchPlan, String> {
+    let mut packs: Vec<(String, Rational)> = Vec::new();
+    let mut lab_seconds = Rational::ZERO;
+    for name in queue {
+        let technology = find(technologies, name)?;
+        lab_seconds += technology.count * technology.time;
+        for &(amount, ref pack) in &technology.ingredients {
+            match packs.iter().position(|&(ref p, _)| p == pack) {
+                Some(i) => packs[i].1 += amount * technology.count,
+                None => packs.push((pack.clone(), amount * technology.count))
+            }
+        }
+    }
+    let speed = lab_speed * (research_bonus + 1);
+    Ok(ResearchPlan {
+        pack_rates: packs.into_iter().map(|(pack, total)| (pack, total / seconds)).collect(),
+        labs: lab_seconds / (speed * seconds)
+    })
+}
+
+/// Labs needed to consume `spm` of every pack in `packs` per minute, when one
+/// research unit takes `unit_time` seconds and consumes one of each pack.
+pub fn plan_spm(spm: Rational, packs: &[String], unit_time: Rational, lab_speed: Rational, research_bonus: Rational) -> ResearchPlan {
+    let units_per_second = spm / 60;
+    let speed = lab_speed * (research_bonus + 1);
+    ResearchPlan {
+        pack_rates: packs.iter().map(|pack| (pack.clone(), units_per_second)).collect(),
+        labs: units_per_second * unit_time / speed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn technology(name: &str, count: i32, time: i32, packs: &[&str], prerequisites: &[&str]) -> (String, Technology) {
+        (name.to_string(), Technology {
+            name: name.to_string(),
+            count: Rational::from(count),
+            time: Rational::from(time),
+            ingredients: packs.iter().map(|x| (Rational::ONE, x.to_string())).collect(),
+            prerequisites: prerequisites.iter().map(|x| x.to_string()).collect()
+        })
+    }
+
+    fn tree() -> HashMap<String, Technology> {
+        vec![
+            technology("automation", 10, 10, &["red"], &[]),
+            technology("logistics", 20, 15, &["red"], &[]),
+            technology("electronics", 30, 15, &["red"], &["automation"]),
+            technology("automation-2", 40, 5, &["red", "green"], &["electronics", "logistics"])
+        ].into_iter().collect()
+    }
+
+    #[test]
+    fn expands_prerequisites_before_technologies() {
+        let queue = expand_queue(&tree(), &["automation-2".to_string()], &HashSet::new()).unwrap();
+        assert_eq!(queue, vec!["automation", "electronics", "logistics", "automation-2"]);
+        let researched = vec!["automation".to_string()].into_iter().collect();
+        let queue = expand_queue(&tree(), &["automation-2".to_string(), "electronics".to_string()], &researched).unwrap();
+        assert_eq!(queue, vec!["electronics", "logistics", "automation-2"]);
+    }
+
+    #[test]
+    fn sums_science_packs_of_the_queue() {
+        let queue: Vec<String> = vec!["automation".to_string(), "automation-2".to_string()];
+        let plan = plan_queue(&tree(), &queue, Rational::from(600), Rational::ONE, Rational::ZERO).unwrap();
+        // 10 + 40 red and 40 green packs, 10 * 10 + 40 * 5 lab seconds in 600 seconds
+        assert_eq!(plan.pack_rates, vec![("red".to_string(), Rational::new(1, 12)), ("green".to_string(), Rational::new(1, 15))]);
+        assert_eq!(plan.labs, Rational::new(1, 2));
+        let plan = plan_queue(&tree(), &queue, Rational::from(600), Rational::from(2), Rational::new(1, 2)).unwrap();
+        assert_eq!(plan.labs, Rational::new(1, 6));
+    }
+
+    #[test]
+    fn reports_unknown_technologies_and_labs() {
+        assert_eq!(expand_queue(&tree(), &["rocketry".to_string()], &HashSet::new()).unwrap_err(), "unknown technology: rocketry");
+        assert!(plan_queue(&tree(), &["rocketry".to_string()], Rational::ONE, Rational::ONE, Rational::ZERO).is_err());
+        assert_eq!(lab_speed(&[], "lab").unwrap(), Rational::ONE);
+        assert!(lab_speed(&[], "biolab").is_err());
+    }
+}
@@ -0,0 +1,391 @@
+//! Dependency graph and production plan for a set of target products.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use structs;
+use preferences::MachinePreferences;
+use rational::{self, Rational};
+
+/// Throughput of a single pump (or offshore pump) in units/s.
+pub const PUMP_THROUGHPUT: u32 = 1200;
+
+/// Maximum throughput of a pipe run of the given length, units/s.
+/// Approximate values for the 0.17+ fluid system from the Factorio wiki.
+pub const PIPE_THROUGHPUT: [(u32, u32); 14] = [
+    (1, 6000), (2, 3000), (7, 2000), (12, 1500), (17, 1200), (20, 1100), (30, 1050),
+    (200, 1000), (261, 800), (300, 750), (400, 600), (500, 500), (600, 450), (1000, 400)
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComponentType {
+    Item,
+    Fluid
+}
+
+#[derive(Debug)]
+pub struct Component {
+    pub a_type: ComponentType,
+    pub name: String,
+    pub amount: Rational,
+    pub min_temperature: Option<Rational>,
+    pub max_temperature: Option<Rational>
+}
+
+/// Recipe normalized to produce one unit of a single product.
+#[derive(Debug)]
+pub struct ProductRecipe {
+    pub output: Rational,
+    pub output_variance: Rational,
+    pub output_type: ComponentType,
+    pub temperature: Option<Rational>,
+    pub time: Rational,
+    pub compounds: Vec<Component>,
+    /// Other products of the recipe, per unit of the product.
+    pub byproducts: Vec<Component>,
+    /// Crafting speed of the machine.
+    pub time_factor: Rational,
+    /// Machine crafting the recipe, or why none can.
+    pub machine: Result<&'static str, String>,
+    pub category: String
+}
+
+#[derive(Debug)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub weight: Rational,
+    pub min_temperature: Option<Rational>,
+    pub max_temperature: Option<Rational>
+}
+
+#[derive(Debug, Default)]
+pub struct Graph {
+    pub vertices: HashSet<String>,
+    pub edges: Vec<Edge>
+}
+
+#[derive(Debug)]
+pub struct Param {
+    pub time: Option<Rational>,
+    pub rate: Rational
+}
+
+#[derive(Debug)]
+pub struct AssemblePlan {
+    pub out: Vec<(String, Param)>,
+    pub compounds: HashMap<String, Param>
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph { vertices: HashSet::new(), edges: Vec::new() }
+    }
+}
+
+pub fn build_dependency_net(dependencies: &HashMap<String, ProductRecipe>, targets: Vec<String>) -> Graph {
+    let mut graph = Graph::new();
+    let mut queue: VecDeque<_> = targets.into_iter().collect();
+    while !queue.is_empty() {
+        let v = queue.pop_front().unwrap();
+        if graph.vertices.contains(&v) {
+            continue;
+        }
+        graph.vertices.insert(v.clone());
+        let recipe = match dependencies.get(&*v) {
+            Some(a) => a,
+            None => continue
+        };
+        for c in recipe.compounds.iter() {
+            graph.edges.push(Edge {
+                from: v.clone(),
+                to: c.name.clone(),
+                weight: c.amount,
+                min_temperature: c.min_temperature,
+                max_temperature: c.max_temperature
+            });
+            queue.push_back(c.name.clone());
+        }
+    }
+    graph
+}
+
+pub fn make_plan(dependencies: &HashMap<String, ProductRecipe>, targets: &HashMap<String, Rational>, graph: Graph) -> AssemblePlan {
+    let mut plan: HashMap<String, Param> = HashMap::new();
+    let mut out: Vec<(String, Param)> = Vec::new();
+    let mut undone = HashSet::new();
+    for s in graph.vertices.iter() {
+        undone.insert(s.clone());
+    }
+
+    while !undone.is_empty() {
+        let mut v = None;
+        for s in &undone {
+            if !undone.contains(s) {
+                break;
+            }
+            let mut flag = true;
+            for edge in &graph.edges {
+                if edge.to == *s && undone.contains(&edge.from) {
+                    flag = false;
+                    break;
+                }
+            }
+            if flag {
+                v = Some(s.clone());
+                break;
+            }
+        }
+        match v {
+            None => panic!("No leaf found with nonempty unevaluated state!"),
+            Some(v) => {
+                undone.remove(&v);
+                let time = dependencies.get(&*v).map(|r| r.time / r.time_factor);
+                let mut rate = Rational::ZERO;
+                for edge in &graph.edges {
+                    if edge.to == v {
+                        rate += edge.weight * plan.get(&edge.from).unwrap().rate;
+                    }
+                }
+                if let Some(target_rate) = targets.get(&v) {
+                    rate += *target_rate;
+                }
+                plan.insert(v.clone(), Param{time: time, rate: rate});
+                out.push((v, Param{time: time, rate: rate}));
+            }
+        };
+    }
+    AssemblePlan{out: out, compounds: plan}
+}
+
+pub fn to_component(comp: &structs::RecipeComponent) -> Component {
+    Component {
+        a_type: if comp.a_type == "fluid" { ComponentType::Fluid } else { ComponentType::Item },
+        name: comp.name.clone(),
+        amount: Rational::from_f64(comp.amount),
+        min_temperature: comp.minimum_temperature.map(Rational::from_f64),
+        max_temperature: comp.maximum_temperature.map(Rational::from_f64)
+    }
+}
+
+/// The recipe used for every product: the one named after the product if
+/// several recipes make it, otherwise the first one.
+pub fn recipes_by_product(recipes: &[structs::Recipe]) -> HashMap<&str, &structs::Recipe> {
+    let mut m: HashMap<&str, &structs::Recipe> = HashMap::new();
+    for r in recipes {
+        for product in &r.products {
+            if !m.contains_key(&product.name[..]) || r.name == product.name {
+                m.insert(&product.name, r);
+            }
+        }
+    }
+    m
+}
+
+/// Builds a planner recipe for every product. If several recipes make the same
+/// product, the one named after the product wins, otherwise the first one.
+pub fn to_product_recipes(recipes: &[structs::Recipe]) -> HashMap<String, ProductRecipe> {
+    to_product_recipes_with(recipes, &MachinePreferences::new())
+}
+
+/// Same as `to_product_recipes`, crafting with the machines of `preferences`.
+pub fn to_product_recipes_with(recipes: &[structs::Recipe], preferences: &MachinePreferences) -> HashMap<String, ProductRecipe> {
+    let mut m: HashMap<String, ProductRecipe> = HashMap::new();
+    for r in recipes {
+        for product in &r.products {
+            if m.contains_key(&product.name) && r.name != product.name {
+                continue;
+            }
+            let compounds: Vec<_> = r.ingredients.iter().map(to_component).collect();
+            let machine = preferences.machine_for(r);
+            let mut recipe = ProductRecipe {
+                output: Rational::from_f64(product.expected_amount()),
+                output_variance: Rational::from_f64(product.variance()),
+                output_type: to_component(product).a_type,
+                temperature: product.temperature.map(Rational::from_f64),
+                time: Rational::from_f64(r.energy_required),
+                compounds: compounds,
+                byproducts: r.products.iter().filter(|x| x.name != product.name).map(|x| Component {
+                    amount: Rational::from_f64(x.expected_amount()),
+                    ..to_component(x)
+                }).collect(),
+                time_factor: machine.as_ref().map(|x| Rational::from_f64(x.crafting_speed)).unwrap_or(Rational::ONE),
+                machine: machine.map(|x| x.name).map_err(|why| format!("recipe {}: {}", r.name, why)),
+                category: r.category.clone()
+            };
+            let output = recipe.output;
+            if output != Rational::ONE {
+                recipe.output /= output;
+                recipe.output_variance /= output;
+                recipe.time /= output;
+                for c in recipe.compounds.iter_mut().chain(recipe.byproducts.iter_mut()) {
+                    c.amount /= output;
+                }
+            }
+            m.insert(product.name.clone(), recipe);
+        }
+    }
+    m
+}
+
+/// Largest factor `whole_machine_scale` scales a plan by.
+pub const MAX_SCALE: i32 = 10;
+
+/// Machines to build for `count` machines' worth of work, and the
+/// utilization of the last one (1 if it's busy all the time).
+pub fn whole_machines(count: Rational) -> (Rational, Rational) {
+    let machines = count.ceil();
+    if machines <= Rational::ZERO {
+        return (Rational::ZERO, Rational::ZERO);
+    }
+    (machines, count - (machines - 1))
+}
+
+/// Factor that scales the machine `counts` of a plan so that the last
+/// machine of every step is at least `min_utilization` (0 to 1) busy: 1 if
+/// they already are, else the one closest to 1 (up to `MAX_SCALE`) at which
+/// every count is whole.
+pub fn whole_machine_scale(counts: &[Rational], min_utilization: Rational) -> Option<Rational> {
+    // Steps without work need no machines at any scale.
+    let counts: Vec<_> = counts.iter().cloned().filter(|&x| x > Rational::ZERO).collect();
+    if counts.iter().all(|&x| whole_machines(x).1 >= min_utilization) {
+        return Some(Rational::ONE);
+    }
+    // Every count is whole at the multiples of lcm(denominators) / gcd(numerators).
+    let mut denominators: i128 = 1;
+    let mut numerators: i128 = 0;
+    for &x in &counts {
+        denominators = (denominators / rational::gcd(denominators, x.denom())).checked_mul(x.denom())?;
+        numerators = rational::gcd(numerators, x.numer());
+    }
+    let lowest = Rational::new(denominators, numerators);
+    // 1 isn't a multiple, or the counts would be whole already
+    let below = lowest.recip().floor() * lowest;
+    let above = below + lowest;
+    let scale = if below > Rational::ZERO && Rational::ONE - below < above - 1 { below } else { above };
+    Some(scale).filter(|&x| x <= Rational::from(MAX_SCALE))
+}
+
+/// Errors for the steps of `plan` that no machine can craft.
+pub fn machine_errors(dependencies: &HashMap<String, ProductRecipe>, plan: &AssemblePlan) -> Vec<String> {
+    plan.out.iter()
+        .filter(|x| x.1.time.is_some())
+        .filter_map(|x| dependencies.get(&x.0).and_then(|r| r.machine.as_ref().err()).cloned())
+        .collect()
+}
+
+/// Rockets per hour and rocket silos for `part_rate` rocket parts/s taking
+/// `part_time` seconds each. A silo doesn't make parts while its rocket
+/// launches, so every rocket takes `ROCKET_LAUNCH_TIME` more.
+pub fn rocket_silos(part_rate: Rational, part_time: Rational, parts_required: Rational) -> (Rational, Rational) {
+    let speed = Rational::from_f64(structs::ROCKET_SILO.crafting_speed);
+    let rockets = part_rate / parts_required;
+    let rocket_time = parts_required * part_time / speed + Rational::from_f64(structs::ROCKET_LAUNCH_TIME);
+    (rockets * 3600, rockets * rocket_time)
+}
+
+/// Fixed-output machines (e.g. offshore pumps) making `rate` units/s of a
+/// raw fluid, if there is one for it.
+pub fn fluid_sources(fluid: &str, rate: Rational) -> Option<(&'static str, Rational)> {
+    structs::fluid_source(fluid).map(|x| (x.name, (rate / Rational::from_f64(x.output)).ceil()))
+}
+
+/// Rates of the byproducts of all plan steps, sorted by name.
+pub fn byproduct_rates(dependencies: &HashMap<String, ProductRecipe>, plan: &AssemblePlan) -> Vec<(String, Rational, ComponentType)> {
+    let mut rates: HashMap<&str, (Rational, ComponentType)> = HashMap::new();
+    for &(ref name, ref param) in &plan.out {
+        if let Some(recipe) = dependencies.get(name) {
+            for c in &recipe.byproducts {
+                rates.entry(&c.name).or_insert((Rational::ZERO, c.a_type)).0 += c.amount * param.rate;
+            }
+        }
+    }
+    let mut out: Vec<_> = rates.into_iter().map(|(name, (rate, a_type))| (name.to_string(), rate, a_type)).collect();
+    out.sort_by(|a, b| a.0.cmp(&b.0));
+    out
+}
+
+/// Names of all components used or produced as fluids.
+pub fn fluid_names(m: &HashMap<String, ProductRecipe>) -> HashSet<String> {
+    let mut set = HashSet::new();
+    for (name, recipe) in m {
+        if recipe.output_type == ComponentType::Fluid {
+            set.insert(name.clone());
+        }
+        for c in &recipe.compounds {
+            if c.a_type == ComponentType::Fluid {
+                set.insert(c.name.clone());
+            }
+        }
+    }
+    set
+}
+
+/// Pumps and pipe length needed to move `rate` units/s of fluid.
+pub fn fluid_logistics(rate: Rational) -> String {
+    let pumps = (rate / PUMP_THROUGHPUT).ceil();
+    let max_length = PIPE_THROUGHPUT.iter().rev().find(|&&(_, flow)| Rational::from(flow) >= rate).map(|&(length, _)| length);
+    match max_length {
+        Some(length) => format!("pumps: {}, single pipe up to {} tiles", pumps, length),
+        None => format!("pumps: {}, parallel pipes: {} (up to 200 tiles each)", pumps, (rate / 1000).ceil())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use data_file;
+    use super::*;
+
+    fn r(num: i128, den: i128) -> Rational {
+        Rational::new(num, den)
+    }
+
+    #[test]
+    fn counts_whole_machines() {
+        assert_eq!(whole_machines(r(7, 3)), (Rational::from(3), r(1, 3)));
+        assert_eq!(whole_machines(Rational::from(2)), (Rational::from(2), Rational::ONE));
+        assert_eq!(whole_machines(Rational::ZERO), (Rational::ZERO, Rational::ZERO));
+    }
+
+    #[test]
+    fn scales_plans_to_whole_machines() {
+        let counts = [r(3, 2), r(3, 4), Rational::ZERO];
+        assert_eq!(whole_machine_scale(&counts, Rational::ZERO), Some(Rational::ONE));
+        // 4/3 makes both counts whole
+        assert_eq!(whole_machine_scale(&counts, Rational::ONE), Some(r(4, 3)));
+        assert_eq!(whole_machine_scale(&counts, r(1, 2)), Some(Rational::ONE));
+        assert_eq!(whole_machine_scale(&counts, r(3, 5)), Some(r(4, 3)));
+        assert_eq!(whole_machine_scale(&[r(9, 10)], Rational::ONE), Some(r(10, 9)));
+        assert_eq!(whole_machine_scale(&[Rational::from(2), Rational::from(3)], Rational::ONE), Some(Rational::ONE));
+        assert_eq!(whole_machine_scale(&[r(1, 100)], Rational::ONE), None);
+        // 2 machines at 6/7 of the rate are closer than 3 at 9/7
+        assert_eq!(whole_machine_scale(&[r(7, 3)], Rational::ONE), Some(r(6, 7)));
+        assert_eq!(whole_machine_scale(&[r(4, 3), Rational::from(2)], Rational::ONE), Some(r(3, 2)));
+        let huge = 1 << 100;
+        assert_eq!(whole_machine_scale(&[r(1, huge + 1), r(1, huge - 1)], Rational::ONE), None);
+    }
+
+    #[test]
+    fn plans_fluid_ingredients_and_products() {
+        let recipes = data_file::read_recipes("format\t8\n\
+            recipe\tacid\nenergy_required\t1\ningredient\tfluid\twater\t100\ningredient\titem\tsulfur\t5\n\
+            product\tfluid\tsulfuric-acid\t50\ttemperature=25\nend\n\
+            recipe\tbattery\nenergy_required\t4\ningredient\tfluid\tsulfuric-acid\t20\tminimum_temperature=15\n\
+            ingredient\titem\tplate\t1\nproduct\titem\tbattery\t1\nend\n").unwrap();
+        let m = to_product_recipes(&recipes);
+        let acid = &m["sulfuric-acid"];
+        assert_eq!((acid.output_type, acid.temperature, acid.time), (ComponentType::Fluid, Some(Rational::from(25)), r(1, 50)));
+        assert_eq!(acid.compounds.iter().map(|c| (&c.name[..], c.a_type, c.amount)).collect::<Vec<_>>(),
+                   vec![("water", ComponentType::Fluid, Rational::from(2)), ("sulfur", ComponentType::Item, r(1, 10))]);
+        let mut fluids: Vec<_> = fluid_names(&m).into_iter().collect();
+        fluids.sort();
+        assert_eq!(fluids, vec!["sulfuric-acid".to_string(), "water".to_string()]);
+
+        let graph = build_dependency_net(&m, vec!["battery".to_string()]);
+        let edge = graph.edges.iter().find(|e| e.to == "sulfuric-acid").unwrap();
+        assert_eq!((edge.min_temperature, edge.max_temperature), (Some(Rational::from(15)), None));
+        let targets = vec![("battery".to_string(), Rational::ONE)].into_iter().collect();
+        let plan = make_plan(&m, &targets, graph);
+        assert_eq!(plan.compounds["sulfuric-acid"].rate, Rational::from(20));
+        assert_eq!(plan.compounds["water"].rate, Rational::from(40));
+        assert_eq!(plan.compounds["sulfur"].rate, Rational::from(2));
+    }
+}
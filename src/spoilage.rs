@@ -0,0 +1,200 @@
+//! Spoilage (Space Age): items with `spoil_ticks` turn into their
+//! `spoil_result` after that time.
+//!
+//! Every item of a plan travels from the machine that makes it to the one that
+//! uses it: `transit` seconds on belts plus a wait in a buffer. Buffer waits
+//! are taken as spread evenly between 0 and twice the mean `buffer` time, so
+//! part of the items may spoil even when the mean age is fine. A product made
+//! from spoiling ingredients starts as spoiled as the worst of them (the game
+//! averages, this errs on the safe side).
+
+use std::collections::HashMap;
+use solver::{build_dependency_net, make_plan, AssemblePlan, ProductRecipe};
+use rational::Rational;
+use structs::Item;
+
+pub const TICKS_PER_SECOND: i32 = 60;
+
+/// Times every item spends between two machines, in seconds.
+#[derive(Debug, Clone, Copy)]
+pub struct Logistics {
+    pub transit: Rational,
+    pub buffer: Rational
+}
+
+/// Spoilage of one item of the plan.
+#[derive(Debug)]
+pub struct ItemSpoilage {
+    pub name: String,
+    /// Spoil time in seconds.
+    pub spoil_time: Rational,
+    /// Spoil progress when made (0 is fresh, 1 spoiled).
+    pub made: Rational,
+    /// Mean spoil progress when used.
+    pub delivered: Rational,
+    /// Fraction of the items that spoil on the way.
+    pub spoiled: Rational,
+    /// Items made per second, including the ones that spoil.
+    pub rate: Rational,
+    pub result: Option<String>
+}
+
+#[derive(Debug)]
+pub struct SpoilagePlan {
+    pub plan: AssemblePlan,
+    pub items: Vec<ItemSpoilage>,
+    /// Spoil results per second, e.g. `spoilage`.
+    pub outputs: Vec<(String, Rational)>,
+    pub warnings: Vec<String>
+}
+
+/// Spoil time in seconds of the items of `items` that spoil.
+pub fn spoil_times(items: &[Item]) -> HashMap<String, Rational> {
+    items.iter().filter_map(|x| x.spoil_ticks.map(|t| (x.name.clone(), Rational::from_f64(t) / TICKS_PER_SECOND))).collect()
+}
+
+/// Fraction of items that spoil if their spoil progress when used is spread
+/// evenly between `low` and `high`.
+fn spoiled_fraction(low: Rational, high: Rational) -> Rational {
+    if high <= low {
+        return if low >= Rational::ONE { Rational::ONE } else { Rational::ZERO };
+    }
+    ((high - 1) / (high - low)).clamp(Rational::ZERO, Rational::ONE)
+}
+
+/// Plans `targets`, making more of every spoiling item to make up for the
+/// ones that spoil between machines (the targets are delivered the same
+/// way). Warns about items that all spoil on the way or arrive with less
+/// than `min_freshness` (0 to 1) left on average.
+pub fn plan_with_spoilage(dependencies: &HashMap<String, ProductRecipe>, items: &[Item], targets: &HashMap<String, Rational>,
+                          logistics: Logistics, min_freshness: Rational) -> SpoilagePlan {
+    let times = spoil_times(items);
+    let results: HashMap<&str, &str> = items.iter()
+        .filter_map(|x| x.spoil_result.as_ref().map(|r| (&x.name[..], &r[..])))
+        .collect();
+    let graph = build_dependency_net(dependencies, targets.keys().cloned().collect());
+    let base = make_plan(dependencies, targets, build_dependency_net(dependencies, targets.keys().cloned().collect()));
+
+    // Spoil progress, ingredients first (the plan lists users before what they use).
+    let mut spoilage: Vec<ItemSpoilage> = Vec::new();
+    let mut warnings = Vec::new();
+    for &(ref name, _) in base.out.iter().rev() {
+        let spoil_time = match times.get(name) {
+            Some(&t) => t,
+            None => continue
+        };
+        let start = graph.edges.iter()
+            .filter(|e| e.from == *name)
+            .filter_map(|e| spoilage.iter().find(|x| x.name == e.to).map(|x| x.delivered))
+            .fold(Rational::ZERO, Rational::max);
+        let low = start + logistics.transit / spoil_time;
+        let high = low + logistics.buffer * 2 / spoil_time;
+        let delivered = low + logistics.buffer / spoil_time;
+        let spoiled = spoiled_fraction(low, high);
+        if spoiled >= Rational::ONE {
+            warnings.push(format!("{} spoils before it is used ({}% spoiled when made)", name, (start * 100).round()));
+        } else if -delivered + 1 < min_freshness {
+            warnings.push(format!("{} arrives with {}% freshness, less than {}%", name,
+                ((-delivered + 1) * 100).round(), (min_freshness * 100).round()));
+        }
+        spoilage.push(ItemSpoilage {
+            name: name.clone(),
+            spoil_time: spoil_time,
+            made: start,
+            delivered: delivered.min(Rational::ONE),
+            spoiled: spoiled,
+            rate: Rational::ZERO,
+            result: results.get(&name[..]).map(|x| x.to_string())
+        });
+    }
+
+    // Extra production for the spoiled items. Users come before what they
+    // use, so this settles after as many rounds as the chain is deep.
+    let mut extra: HashMap<String, Rational> = HashMap::new();
+    let mut plan = base;
+    for _ in 0..=spoilage.len() {
+        let mut adjusted = targets.clone();
+        for (name, x) in &extra {
+            *adjusted.entry(name.clone()).or_insert(Rational::ZERO) += *x;
+        }
+        plan = make_plan(dependencies, &adjusted, build_dependency_net(dependencies, targets.keys().cloned().collect()));
+        let mut next = HashMap::new();
+        for item in spoilage.iter().filter(|x| x.spoiled > Rational::ZERO && x.spoiled < Rational::ONE) {
+            let used = plan.compounds[&item.name].rate - extra.get(&item.name).cloned().unwrap_or_default();
+            next.insert(item.name.clone(), used * item.spoiled / (-item.spoiled + 1));
+        }
+        let settled = next.iter().all(|(name, &x)| extra.get(name).is_some_and(|&y| (x - y).abs() * 100_000 <= x.max(Rational::ONE)));
+        extra = next;
+        if settled {
+            break;
+        }
+    }
+
+    let mut outputs: HashMap<String, Rational> = HashMap::new();
+    for item in &mut spoilage {
+        item.rate = plan.compounds[&item.name].rate;
+        if let Some(ref result) = item.result {
+            if item.spoiled > Rational::ZERO {
+                *outputs.entry(result.clone()).or_insert(Rational::ZERO) += item.rate * item.spoiled;
+            }
+        }
+    }
+    spoilage.reverse();
+    let mut outputs: Vec<_> = outputs.into_iter().collect();
+    outputs.sort_by(|a, b| a.0.cmp(&b.0));
+    SpoilagePlan { plan: plan, items: spoilage, outputs: outputs, warnings: warnings }
+}
+
+#[cfg(test)]
+mod tests {
+    use data_file;
+    use solver::to_product_recipes;
+    use super::*;
+
+    /// Two fruits spoiling in 60 and 120 seconds, mashed with or without a plate.
+    const DATA: &'static str = "format\t8\n\
+        recipe\tmash\nenergy_required\t1\ningredient\titem\tfruit-a\t1\ningredient\titem\tfruit-b\t1\nproduct\titem\tmash\t1\nend\n\
+        recipe\tjam\nenergy_required\t1\ningredient\titem\tfruit-a\t1\ningredient\titem\tplate\t1\nproduct\titem\tjam\t1\nend\n\
+        item\tfruit-a\nspoil_ticks\t3600\nspoil_result\tspoilage\nend\n\
+        item\tfruit-b\nspoil_ticks\t7200\nspoil_result\tspoilage\nend\n\
+        item\tmash\nspoil_ticks\t72000\nend\n\
+        item\tjam\nspoil_ticks\t72000\nend\n";
+
+    fn plan(target: &str, logistics: Logistics) -> SpoilagePlan {
+        let data = data_file::read_data(DATA).unwrap();
+        let targets = vec![(target.to_string(), Rational::ONE)].into_iter().collect();
+        plan_with_spoilage(&to_product_recipes(&data.recipes), &data.items, &targets, logistics, Rational::ZERO)
+    }
+
+    fn item<'p>(plan: &'p SpoilagePlan, name: &str) -> &'p ItemSpoilage {
+        plan.items.iter().find(|x| x.name == name).unwrap()
+    }
+
+    fn r(num: i128, den: i128) -> Rational {
+        Rational::new(num, den)
+    }
+
+    #[test]
+    fn products_start_as_spoiled_as_the_worst_ingredient() {
+        let result = plan("mash", Logistics { transit: Rational::from(6), buffer: Rational::ZERO });
+        assert_eq!(item(&result, "fruit-a").delivered, r(1, 10));
+        assert_eq!(item(&result, "fruit-b").delivered, r(1, 20));
+        assert_eq!(item(&result, "mash").made, r(1, 10));
+        assert_eq!(item(&result, "mash").delivered, r(1, 10) + r(6, 1200));
+        assert!(result.items.iter().all(|x| x.spoiled == Rational::ZERO));
+        assert!(result.outputs.is_empty());
+    }
+
+    #[test]
+    fn ingredients_that_dont_spoil_are_left_out() {
+        // buffer waits of 0 to 66 seconds: 1/11 of the fruits are older than
+        // the 60 seconds they keep, so 11/10 fruits are made for each jam
+        let result = plan("jam", Logistics { transit: Rational::ZERO, buffer: Rational::from(33) });
+        assert!(result.items.iter().all(|x| x.name != "plate"));
+        let fruit = item(&result, "fruit-a");
+        assert_eq!((fruit.spoiled, fruit.rate), (r(1, 11), r(11, 10)));
+        assert_eq!(item(&result, "jam").made, r(33, 60));
+        assert_eq!(result.outputs, vec![("spoilage".to_string(), r(1, 10))]);
+        assert_eq!(result.plan.compounds["plate"].rate, Rational::ONE);
+    }
+}
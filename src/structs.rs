@@ -1,18 +1,95 @@
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RecipeComponent {
     pub a_type: String,
     pub name: String,
-    pub amount: f64
+    pub amount: f64,
+    pub amount_min: Option<f64>,
+    pub amount_max: Option<f64>,
+    pub probability: f64,
+    pub temperature: Option<f64>,
+    pub minimum_temperature: Option<f64>,
+    pub maximum_temperature: Option<f64>
 }
 
-#[derive(Debug)]
+impl RecipeComponent {
+    /// Expected amount per craft, taking `probability` and `amount_min`/`amount_max` into account.
+    pub fn expected_amount(&self) -> f64 {
+        match (self.amount_min, self.amount_max) {
+            (Some(min), Some(max)) => self.probability * (min + max) / 2.0,
+            _ => self.probability * self.amount
+        }
+    }
+
+    /// Variance of the amount per craft. Ranged amounts are uniformly distributed integers.
+    pub fn variance(&self) -> f64 {
+        let (mean, range_variance) = match (self.amount_min, self.amount_max) {
+            (Some(min), Some(max)) => ((min + max) / 2.0, ((max - min + 1.0).powi(2) - 1.0) / 12.0),
+            _ => (self.amount, 0.0)
+        };
+        let p = self.probability;
+        p * (range_variance + mean * mean) - p * p * mean * mean
+    }
+}
+
+/// Surface property range a recipe can be crafted in (Factorio 2.0),
+/// e.g. `pressure` between 0 and 0 for space platforms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceCondition {
+    pub property: String,
+    pub min: Option<f64>,
+    pub max: Option<f64>
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Recipe {
     pub name: String,
     pub category: String,
     pub products: Vec<RecipeComponent>,
     pub ingredients: Vec<RecipeComponent>,
     pub energy_required: f64,
-    pub enabled: bool
+    pub enabled: bool,
+    pub allow_productivity: bool,
+    /// Productivity bonus cap (Factorio 2.0), `None` for legacy recipes.
+    pub maximum_productivity: Option<f64>,
+    pub surface_conditions: Vec<SurfaceCondition>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Technology {
+    pub name: String,
+    pub unit_count: f64,
+    pub unit_time: f64,
+    pub unit_ingredients: Vec<RecipeComponent>,
+    pub prerequisites: Vec<String>
+}
+
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub a_type: String,
+    pub name: String,
+    pub stack_size: f64,
+    pub subgroup: Option<String>,
+    pub place_result: Option<String>,
+    pub fuel_value: Option<String>,
+    /// Time to spoil (Space Age), in ticks.
+    pub spoil_ticks: Option<f64>,
+    /// Item a spoiled item turns into.
+    pub spoil_result: Option<String>,
+    /// Weight for rocket capacity (Factorio 2.0), 1000 per kg.
+    pub weight: Option<f64>
+}
+
+/// Entity prototype, with the fields of crafting machines, labs and mining drills.
+#[derive(Debug)]
+pub struct Entity {
+    pub a_type: String,
+    pub name: String,
+    pub crafting_speed: Option<f64>,
+    pub crafting_categories: Vec<String>,
+    pub ingredient_count: Option<f64>,
+    pub researching_speed: Option<f64>,
+    pub mining_speed: Option<f64>,
+    pub energy_usage: Option<String>
 }
 
 #[derive(Debug)]
@@ -21,12 +98,29 @@ pub enum Power {
     Electric(i64)
 }
 
-use Power::*;
+use self::Power::*;
+use self::RecipeMode::*;
+
+/// How a crafting machine gets its recipe.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecipeMode {
+    /// Set by the player (assembling machines).
+    Chosen,
+    /// Picked from the input (furnaces, recyclers).
+    Automatic,
+    /// Always the same recipe (rocket silo).
+    Fixed(&'static str)
+}
 
 pub struct TransformMachine {
     pub name: &'static str,
     pub energy_consumption: Power,
     pub crafting_speed: f64,
+    pub crafting_categories: &'static [&'static str],
+    pub recipe_mode: RecipeMode,
+    /// Most ingredients a recipe crafted here may have before Factorio 1.1,
+    /// used for data without machine blocks.
+    pub ingredient_count: i32,
     pub module_slots: i32,
     pub pollution: f64
 }
@@ -40,6 +134,23 @@ pub struct MiningMachine {
     pub pollution: f64
 }
 
+pub struct Lab {
+    pub name: &'static str,
+    pub energy_consumption: Power,
+    pub researching_speed: f64,
+    pub module_slots: i32,
+    pub pollution: f64
+}
+
+/// Module effects at normal quality, as fractions (0.1 is +10%).
+pub struct Module {
+    pub name: &'static str,
+    pub speed: f64,
+    pub productivity: f64,
+    pub quality: f64,
+    pub consumption: f64
+}
+
 pub trait Machine {
     fn name(&self) -> String;
     fn energy_consumption(&self) -> &Power;
@@ -65,6 +176,24 @@ impl Machine for TransformMachine {
     }
 }
 
+impl Machine for Lab {
+    fn name(&self) -> String {
+        self.name.to_string()
+    }
+
+    fn energy_consumption(&self) -> &Power {
+        &self.energy_consumption
+    }
+
+    fn module_slots(&self) -> i32 {
+        self.module_slots
+    }
+
+    fn pollution(&self) -> f64 {
+        self.pollution
+    }
+}
+
 impl Machine for MiningMachine {
     fn name(&self) -> String {
         self.name.to_string()
@@ -87,6 +216,9 @@ pub const CHEMICAL_PLANT: TransformMachine = TransformMachine {
     name: "chemical-plant",
     energy_consumption: Electric(210_000),
     crafting_speed: 1.25,
+    crafting_categories: &["chemistry", "chemistry-or-cryogenics"],
+    recipe_mode: Chosen,
+    ingredient_count: 4,
     module_slots: 2,
     pollution: 1.8
 };
@@ -95,6 +227,9 @@ pub const ELECTRIC_FURNACE: TransformMachine = TransformMachine {
     name: "electric-furnace",
     energy_consumption: Electric(180_000),
     crafting_speed: 2.0,
+    crafting_categories: &["smelting"],
+    recipe_mode: Automatic,
+    ingredient_count: 1,
     module_slots: 2,
     pollution: 0.9
 };
@@ -103,6 +238,9 @@ pub const STEEL_FURNACE: TransformMachine = TransformMachine {
     name: "steel-furnace",
     energy_consumption: Burner(180_000),
     crafting_speed: 2.0,
+    crafting_categories: &["smelting"],
+    recipe_mode: Automatic,
+    ingredient_count: 1,
     module_slots: 0,
     pollution: 3.6
 };
@@ -111,6 +249,9 @@ pub const STONE_FURNACE: TransformMachine = TransformMachine {
     name: "stone-furnace",
     energy_consumption: Burner(180_000),
     crafting_speed: 1.0,
+    crafting_categories: &["smelting"],
+    recipe_mode: Automatic,
+    ingredient_count: 1,
     module_slots: 0,
     pollution: 1.8
 };
@@ -119,6 +260,10 @@ pub const ASSEMBLING_MACHINE_1: TransformMachine = TransformMachine {
     name: "assembling-machine-1",
     energy_consumption: Electric(90_000),
     crafting_speed: 0.5,
+    crafting_categories: &["crafting", "basic-crafting", "advanced-crafting", "electronics", "pressing", "metallurgy-or-assembling",
+                           "organic-or-assembling"],
+    recipe_mode: Chosen,
+    ingredient_count: 2,
     module_slots: 0,
     pollution: 3.0
 };
@@ -127,6 +272,11 @@ pub const ASSEMBLING_MACHINE_2: TransformMachine = TransformMachine {
     name: "assembling-machine-2",
     energy_consumption: Electric(150_000),
     crafting_speed: 0.75,
+    crafting_categories: &["crafting", "basic-crafting", "advanced-crafting", "crafting-with-fluid", "electronics",
+                           "electronics-with-fluid", "pressing", "metallurgy-or-assembling", "organic-or-assembling",
+                           "crafting-with-fluid-or-metallurgy"],
+    recipe_mode: Chosen,
+    ingredient_count: 4,
     module_slots: 2,
     pollution: 2.4
 };
@@ -135,6 +285,11 @@ pub const ASSEMBLING_MACHINE_3: TransformMachine = TransformMachine {
     name: "assembling-machine-3",
     energy_consumption: Electric(210_000),
     crafting_speed: 1.25,
+    crafting_categories: &["crafting", "basic-crafting", "advanced-crafting", "crafting-with-fluid", "electronics",
+                           "electronics-with-fluid", "pressing", "metallurgy-or-assembling", "organic-or-assembling",
+                           "crafting-with-fluid-or-metallurgy"],
+    recipe_mode: Chosen,
+    ingredient_count: 6,
     module_slots: 4,
     pollution: 1.8
 };
@@ -143,10 +298,99 @@ pub const OIL_REFINERY: TransformMachine = TransformMachine {
     name: "oil-refinery",
     energy_consumption: Electric(420_000),
     crafting_speed: 1.0,
+    crafting_categories: &["oil-processing"],
+    recipe_mode: Chosen,
+    ingredient_count: 3,
     module_slots: 2,
     pollution: 3.6
 };
 
+pub const FOUNDRY: TransformMachine = TransformMachine {
+    name: "foundry",
+    energy_consumption: Electric(2_500_000),
+    crafting_speed: 4.0,
+    crafting_categories: &["metallurgy", "pressing", "metallurgy-or-assembling", "crafting-with-fluid-or-metallurgy"],
+    recipe_mode: Chosen,
+    ingredient_count: 255,
+    module_slots: 4,
+    pollution: 6.0
+};
+
+/// Fueled by nutrients.
+pub const BIOCHAMBER: TransformMachine = TransformMachine {
+    name: "biochamber",
+    energy_consumption: Burner(500_000),
+    crafting_speed: 2.0,
+    crafting_categories: &["organic", "organic-or-hand-crafting", "organic-or-assembling"],
+    recipe_mode: Chosen,
+    ingredient_count: 255,
+    module_slots: 4,
+    pollution: -1.0
+};
+
+pub const RECYCLER: TransformMachine = TransformMachine {
+    name: "recycler",
+    energy_consumption: Electric(180_000),
+    crafting_speed: 0.5,
+    crafting_categories: &["recycling", "recycling-or-hand-crafting"],
+    recipe_mode: Automatic,
+    ingredient_count: 1,
+    module_slots: 4,
+    pollution: 0.0
+};
+
+pub const ROCKET_SILO: TransformMachine = TransformMachine {
+    name: "rocket-silo",
+    energy_consumption: Electric(250_000),
+    crafting_speed: 1.0,
+    crafting_categories: &["rocket-building"],
+    recipe_mode: Fixed("rocket-part"),
+    ingredient_count: 3,
+    module_slots: 4,
+    pollution: 0.0
+};
+
+/// Rocket parts for a rocket in the game version the data comes from: 100
+/// up to 1.1 (and for unknown versions), 50 since 2.0.
+pub fn rocket_parts_required(game_version: Option<&str>) -> u32 {
+    let major = game_version.and_then(|x| x.split('.').next()).and_then(|x| x.parse::<u32>().ok());
+    match major {
+        Some(major) if major >= 2 => 50,
+        _ => 100
+    }
+}
+
+/// Time the rocket silo can't make parts while a rocket launches, in seconds.
+pub const ROCKET_LAUNCH_TIME: f64 = 41.25;
+
+/// Machine that makes a fluid out of nothing at a fixed rate.
+pub struct FluidSource {
+    pub name: &'static str,
+    pub fluid: &'static str,
+    /// Units/s.
+    pub output: f64,
+    pub energy_consumption: Power
+}
+
+pub const OFFSHORE_PUMP: FluidSource = FluidSource {
+    name: "offshore-pump",
+    fluid: "water",
+    output: 1200.0,
+    energy_consumption: Electric(0)
+};
+
+pub const FLUID_SOURCES: &'static [&'static FluidSource] = &[
+    &OFFSHORE_PUMP
+];
+
+pub const LAB: Lab = Lab {
+    name: "lab",
+    energy_consumption: Electric(60_000),
+    researching_speed: 1.0,
+    module_slots: 2,
+    pollution: 0.0
+};
+
 pub const ELECTRIC_MINING_DRILL: MiningMachine = MiningMachine {
     name: "electric-mining-drill",
     energy_consumption: Electric(90_000),
@@ -163,7 +407,7 @@ pub const BURNER_MINING_DRILL: MiningMachine = MiningMachine {
     mining_power: 2.5,
     module_slots: 0,
     pollution: 10.0
-}; 
+};
 
 pub const PUMPJACK: MiningMachine = MiningMachine {
     name: "pumpjack",
@@ -172,4 +416,117 @@ pub const PUMPJACK: MiningMachine = MiningMachine {
     mining_power: 2.0,
     module_slots: 2,
     pollution: 9.0
-};
\ No newline at end of file
+};
+pub const TRANSFORM_MACHINES: &'static [&'static TransformMachine] = &[
+    &CHEMICAL_PLANT,
+    &ELECTRIC_FURNACE,
+    &STEEL_FURNACE,
+    &STONE_FURNACE,
+    &ASSEMBLING_MACHINE_1,
+    &ASSEMBLING_MACHINE_2,
+    &ASSEMBLING_MACHINE_3,
+    &OIL_REFINERY,
+    &FOUNDRY,
+    &BIOCHAMBER,
+    &RECYCLER,
+    &ROCKET_SILO
+];
+
+pub const MINING_MACHINES: &'static [&'static MiningMachine] = &[
+    &ELECTRIC_MINING_DRILL,
+    &BURNER_MINING_DRILL,
+    &PUMPJACK
+];
+
+pub fn find_transform_machine(name: &str) -> Option<&'static TransformMachine> {
+    TRANSFORM_MACHINES.iter().copied().find(|x| x.name == name)
+}
+
+/// Source of a fluid that isn't made by a recipe, e.g. the offshore pump for water.
+pub fn fluid_source(fluid: &str) -> Option<&'static FluidSource> {
+    FLUID_SOURCES.iter().copied().find(|x| x.fluid == fluid)
+}
+
+/// Machines that can craft recipes of `category`.
+pub fn machines_for_category(category: &str) -> Vec<&'static TransformMachine> {
+    TRANSFORM_MACHINES.iter().copied().filter(|x| x.crafting_categories.contains(&category)).collect()
+}
+
+/// Machine that always crafts `recipe`, e.g. the rocket silo for `rocket-part`.
+pub fn fixed_recipe_machine(recipe: &str) -> Option<&'static TransformMachine> {
+    TRANSFORM_MACHINES.iter().copied().find(|x| match x.recipe_mode { Fixed(r) => r == recipe, _ => false })
+}
+
+/// Whether the machines crafting `category` pick their recipe from their
+/// input, like furnaces for `smelting`.
+pub fn picks_recipe_from_input(category: &str) -> bool {
+    let machines = machines_for_category(category);
+    !machines.is_empty() && machines.iter().all(|x| x.recipe_mode == Automatic)
+}
+
+pub const SPEED_MODULE: Module = Module { name: "speed-module", speed: 0.2, productivity: 0.0, quality: -0.01, consumption: 0.5 };
+pub const SPEED_MODULE_2: Module = Module { name: "speed-module-2", speed: 0.3, productivity: 0.0, quality: -0.015, consumption: 0.6 };
+pub const SPEED_MODULE_3: Module = Module { name: "speed-module-3", speed: 0.5, productivity: 0.0, quality: -0.025, consumption: 0.7 };
+pub const PRODUCTIVITY_MODULE: Module = Module { name: "productivity-module", speed: -0.05, productivity: 0.04, quality: 0.0, consumption: 0.4 };
+pub const PRODUCTIVITY_MODULE_2: Module = Module { name: "productivity-module-2", speed: -0.1, productivity: 0.06, quality: 0.0, consumption: 0.6 };
+pub const PRODUCTIVITY_MODULE_3: Module = Module { name: "productivity-module-3", speed: -0.15, productivity: 0.1, quality: 0.0, consumption: 0.8 };
+pub const EFFICIENCY_MODULE: Module = Module { name: "efficiency-module", speed: 0.0, productivity: 0.0, quality: 0.0, consumption: -0.3 };
+pub const EFFICIENCY_MODULE_2: Module = Module { name: "efficiency-module-2", speed: 0.0, productivity: 0.0, quality: 0.0, consumption: -0.4 };
+pub const EFFICIENCY_MODULE_3: Module = Module { name: "efficiency-module-3", speed: 0.0, productivity: 0.0, quality: 0.0, consumption: -0.5 };
+pub const QUALITY_MODULE: Module = Module { name: "quality-module", speed: -0.05, productivity: 0.0, quality: 0.01, consumption: 0.0 };
+pub const QUALITY_MODULE_2: Module = Module { name: "quality-module-2", speed: -0.05, productivity: 0.0, quality: 0.02, consumption: 0.0 };
+pub const QUALITY_MODULE_3: Module = Module { name: "quality-module-3", speed: -0.05, productivity: 0.0, quality: 0.025, consumption: 0.0 };
+
+pub const MODULES: &'static [&'static Module] = &[
+    &SPEED_MODULE,
+    &SPEED_MODULE_2,
+    &SPEED_MODULE_3,
+    &PRODUCTIVITY_MODULE,
+    &PRODUCTIVITY_MODULE_2,
+    &PRODUCTIVITY_MODULE_3,
+    &EFFICIENCY_MODULE,
+    &EFFICIENCY_MODULE_2,
+    &EFFICIENCY_MODULE_3,
+    &QUALITY_MODULE,
+    &QUALITY_MODULE_2,
+    &QUALITY_MODULE_3
+];
+
+pub fn find_module(name: &str) -> Option<&'static Module> {
+    MODULES.iter().copied().find(|x| x.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use builtin;
+    use solver;
+    use super::*;
+
+    #[test]
+    fn rockets_take_fewer_parts_since_2_0() {
+        assert_eq!(rocket_parts_required(Some("1.1.110")), 100);
+        assert_eq!(rocket_parts_required(Some("2.0.55")), 50);
+        assert_eq!(rocket_parts_required(Some("2.1")), 50);
+        assert_eq!(rocket_parts_required(None), 100);
+        assert_eq!(rocket_parts_required(Some("custom")), 100);
+    }
+
+    #[test]
+    fn computes_the_variance_of_random_products() {
+        let data = builtin::data_set("1.1").unwrap();
+        let uranium = data.recipes.iter().find(|r| r.name == "uranium-processing").unwrap();
+        let u235 = uranium.products.iter().find(|c| c.name == "uranium-235").unwrap();
+        assert!((u235.expected_amount() - 0.007).abs() < 1e-12);
+        assert!((u235.variance() - 0.007 * 0.993).abs() < 1e-12);
+        // per unit made, the variance is (1 - p)
+        let m = solver::to_product_recipes(&data.recipes);
+        assert!((m["uranium-235"].output_variance.to_f64() - 0.993).abs() < 1e-6);
+
+        let ranged = RecipeComponent { amount_min: Some(1.0), amount_max: Some(3.0), probability: 0.5, ..u235.clone() };
+        // amounts 1..3 have mean 2 and variance 2/3, and half the crafts make nothing
+        assert_eq!(ranged.expected_amount(), 1.0);
+        assert!((ranged.variance() - (0.5 * (2.0 / 3.0 + 4.0) - 1.0)).abs() < 1e-12);
+        let fixed = RecipeComponent { probability: 1.0, ..u235.clone() };
+        assert_eq!(fixed.variance(), 0.0);
+    }
+}
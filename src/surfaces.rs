@@ -0,0 +1,232 @@
+//! Surfaces (Space Age): planets and space platforms, and plans split
+//! across them.
+//!
+//! Every recipe step of a plan is placed on a surface whose properties meet
+//! the recipe's `surface_conditions`; raw resources are taken where they are
+//! used. Items used on another surface than the one that makes them are
+//! shipped: planets launch them in rockets, space platforms drop them.
+
+use std::collections::HashMap;
+use solver::{build_dependency_net, fluid_names, make_plan, recipes_by_product, ProductRecipe};
+use rational::Rational;
+use structs::{Item, Recipe};
+
+#[derive(Debug)]
+pub struct Surface {
+    pub name: &'static str,
+    /// Values of surface properties, e.g. `("pressure", 1000.0)`.
+    pub properties: &'static [(&'static str, f64)],
+    pub is_platform: bool
+}
+
+pub const NAUVIS: Surface = Surface {
+    name: "nauvis",
+    properties: &[("pressure", 1000.0), ("magnetic-field", 90.0), ("gravity", 10.0), ("solar-power", 100.0)],
+    is_platform: false
+};
+
+pub const VULCANUS: Surface = Surface {
+    name: "vulcanus",
+    properties: &[("pressure", 4000.0), ("magnetic-field", 25.0), ("gravity", 40.0), ("solar-power", 400.0)],
+    is_platform: false
+};
+
+pub const FULGORA: Surface = Surface {
+    name: "fulgora",
+    properties: &[("pressure", 800.0), ("magnetic-field", 99.0), ("gravity", 8.0), ("solar-power", 20.0)],
+    is_platform: false
+};
+
+pub const GLEBA: Surface = Surface {
+    name: "gleba",
+    properties: &[("pressure", 2000.0), ("magnetic-field", 10.0), ("gravity", 20.0), ("solar-power", 50.0)],
+    is_platform: false
+};
+
+pub const AQUILO: Surface = Surface {
+    name: "aquilo",
+    properties: &[("pressure", 300.0), ("magnetic-field", 10.0), ("gravity", 15.0), ("solar-power", 1.0)],
+    is_platform: false
+};
+
+pub const SPACE_PLATFORM: Surface = Surface {
+    name: "space-platform",
+    properties: &[("pressure", 0.0), ("magnetic-field", 0.0), ("gravity", 0.0), ("solar-power", 300.0)],
+    is_platform: true
+};
+
+pub const SURFACES: &'static [&'static Surface] = &[
+    &NAUVIS,
+    &VULCANUS,
+    &FULGORA,
+    &GLEBA,
+    &AQUILO,
+    &SPACE_PLATFORM
+];
+
+/// Weight a rocket lifts, 1000 per kg.
+pub const ROCKET_LIFT_WEIGHT: f64 = 1_000_000.0;
+
+/// Weight of items without a `weight` in the data.
+pub const DEFAULT_ITEM_WEIGHT: f64 = 100.0;
+
+pub fn find_surface(name: &str) -> Option<&'static Surface> {
+    SURFACES.iter().copied().find(|x| x.name == name)
+}
+
+impl Surface {
+    /// Value of a surface property. Properties a surface doesn't list have
+    /// their default value, which is the value on Nauvis.
+    pub fn property(&self, name: &str) -> f64 {
+        self.properties.iter().chain(NAUVIS.properties.iter())
+            .find(|x| x.0 == name).map(|x| x.1).unwrap_or(0.0)
+    }
+
+    /// Whether `recipe` can be crafted here.
+    pub fn allows(&self, recipe: &Recipe) -> bool {
+        recipe.surface_conditions.iter().all(|c| {
+            let value = self.property(&c.property);
+            c.min.is_none_or(|min| value >= min) && c.max.is_none_or(|max| value <= max)
+        })
+    }
+}
+
+/// Items (or fluids) moved from one surface to another.
+#[derive(Debug)]
+pub struct Shipment {
+    pub item: String,
+    pub from: &'static str,
+    pub to: &'static str,
+    /// Items per second.
+    pub rate: Rational,
+    /// Items per rocket, `None` for fluids and for drops from platforms.
+    pub per_rocket: Option<Rational>,
+    pub launches_per_hour: Rational,
+    /// Whether the item has no weight in the data and the default is used.
+    pub default_weight: bool
+}
+
+#[derive(Debug)]
+pub struct SurfacePlan {
+    /// Surface of every component of the plan.
+    pub placement: HashMap<String, &'static Surface>,
+    pub shipments: Vec<Shipment>,
+    pub warnings: Vec<String>
+}
+
+/// Places the steps of the plan for `targets` on surfaces and finds the shipments between
+/// them. Targets are delivered to `home`. Steps in `chosen` go to the given
+/// surface, the others to `home` if their recipe is allowed there, otherwise
+/// to the first surface that allows it.
+pub fn split_plan(dependencies: &HashMap<String, ProductRecipe>, recipes: &[Recipe], items: &[Item],
+                  targets: &HashMap<String, Rational>, home: &'static Surface, chosen: &HashMap<String, &'static Surface>) -> SurfacePlan {
+    let graph = build_dependency_net(dependencies, targets.keys().cloned().collect());
+    let plan = make_plan(dependencies, targets, build_dependency_net(dependencies, targets.keys().cloned().collect()));
+    let fluids = fluid_names(dependencies);
+    let sources = recipes_by_product(recipes);
+    let mut placement: HashMap<String, &'static Surface> = HashMap::new();
+    let mut warnings = Vec::new();
+    for &(ref name, ref param) in &plan.out {
+        let recipe = match sources.get(&name[..]) {
+            Some(recipe) if param.time.is_some() => recipe,
+            _ => continue
+        };
+        let surface = match chosen.get(name) {
+            Some(&surface) => {
+                if !surface.allows(recipe) {
+                    warnings.push(format!("recipe {} is not allowed on {}", recipe.name, surface.name));
+                }
+                surface
+            },
+            None if home.allows(recipe) => home,
+            None => match SURFACES.iter().copied().find(|x| x.allows(recipe)) {
+                Some(surface) => surface,
+                None => {
+                    warnings.push(format!("recipe {} is not allowed on any surface", recipe.name));
+                    home
+                }
+            }
+        };
+        placement.insert(name.clone(), surface);
+    }
+    // Raw resources are taken where they are used (the plan lists users first).
+    for &(ref name, _) in &plan.out {
+        if placement.contains_key(name) {
+            continue;
+        }
+        let user = graph.edges.iter().find(|e| e.to == *name).and_then(|e| placement.get(&e.from).copied());
+        placement.insert(name.clone(), user.unwrap_or(home));
+    }
+
+    let mut flows: HashMap<(String, &'static str, &'static str), Rational> = HashMap::new();
+    for edge in &graph.edges {
+        let (from, to) = (placement[&edge.to], placement[&edge.from]);
+        if from.name != to.name {
+            *flows.entry((edge.to.clone(), from.name, to.name)).or_insert(Rational::ZERO) += edge.weight * plan.compounds[&edge.from].rate;
+        }
+    }
+    for (name, &rate) in targets {
+        let from = placement[name];
+        if from.name != home.name {
+            *flows.entry((name.clone(), from.name, home.name)).or_insert(Rational::ZERO) += rate;
+        }
+    }
+
+    let mut shipments: Vec<Shipment> = flows.into_iter().map(|((item, from, to), rate)| {
+        let weight = items.iter().find(|x| x.name == item).and_then(|x| x.weight);
+        let dropped = find_surface(from).is_some_and(|x| x.is_platform);
+        let per_rocket = if fluids.contains(&item) || dropped {
+            None
+        } else {
+            Some((Rational::from_f64(ROCKET_LIFT_WEIGHT) / Rational::from_f64(weight.unwrap_or(DEFAULT_ITEM_WEIGHT))).floor().max(Rational::ONE))
+        };
+        Shipment {
+            launches_per_hour: per_rocket.map(|n| rate * 3600 / n).unwrap_or_default(),
+            item: item,
+            from: from,
+            to: to,
+            rate: rate,
+            per_rocket: per_rocket,
+            default_weight: weight.is_none()
+        }
+    }).collect();
+    shipments.sort_by(|a, b| (a.from, a.to, &a.item).cmp(&(b.from, b.to, &b.item)));
+    for s in shipments.iter().filter(|s| fluids.contains(&s.item)) {
+        warnings.push(format!("fluid {} can't be shipped from {} to {}, make it there or ship it in barrels", s.item, s.from, s.to));
+    }
+    for s in shipments.iter().filter(|s| s.per_rocket.is_some() && s.default_weight) {
+        warnings.push(format!("{} has no weight in the data, its rocket capacity assumes {} per item", s.item, DEFAULT_ITEM_WEIGHT));
+    }
+    SurfacePlan { placement: placement, shipments: shipments, warnings: warnings }
+}
+
+#[cfg(test)]
+mod tests {
+    use data_file;
+    use solver::to_product_recipes;
+    use super::*;
+
+    /// A gadget made on Nauvis from plates and parts that are made on Vulcanus.
+    const DATA: &'static str = "format\t8\n\
+        recipe\tgadget\nenergy_required\t1\ningredient\titem\ttungsten-plate\t2\ningredient\titem\tpart\t1\nproduct\titem\tgadget\t1\nend\n\
+        recipe\ttungsten-plate\nenergy_required\t1\ningredient\titem\ttungsten-ore\t4\nproduct\titem\ttungsten-plate\t1\n\
+        surface_condition\tpressure\t4000\t4000\nend\n\
+        recipe\tpart\nenergy_required\t1\ningredient\titem\ttungsten-ore\t1\nproduct\titem\tpart\t1\n\
+        surface_condition\tpressure\t4000\t-\nend\n\
+        item\ttungsten-plate\nweight\t20000\nend\n";
+
+    #[test]
+    fn ships_items_in_rockets_by_weight() {
+        let data = data_file::read_data(DATA).unwrap();
+        let targets = vec![("gadget".to_string(), Rational::ONE)].into_iter().collect();
+        let plan = split_plan(&to_product_recipes(&data.recipes), &data.recipes, &data.items, &targets, &NAUVIS, &HashMap::new());
+        assert_eq!(plan.placement["gadget"].name, "nauvis");
+        assert_eq!(plan.placement["tungsten-ore"].name, "vulcanus");
+        let shipped: Vec<_> = plan.shipments.iter().map(|s| (&s.item[..], s.from, s.to, s.rate, s.per_rocket, s.launches_per_hour)).collect();
+        assert_eq!(shipped, vec![
+            ("part", "vulcanus", "nauvis", Rational::ONE, Some(Rational::from(10000)), Rational::new(9, 25)),
+            ("tungsten-plate", "vulcanus", "nauvis", Rational::from(2), Some(Rational::from(50)), Rational::from(144))
+        ]);
+        assert_eq!(plan.warnings, vec!["part has no weight in the data, its rocket capacity assumes 100 per item"]);
+    }
+}